@@ -1,246 +1,4446 @@
 #![warn(missing_docs)]
 //! A couple useful thiings for my Discord bots
 
-use std::mem::replace;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{self, Display};
+#[cfg(feature = "futures")]
+use std::future::Future;
+use std::mem::{replace, take};
+use std::ops::{Bound, RangeBounds, RangeInclusive};
+use std::time::Duration;
 use std::vec::IntoIter as VecIntoIter;
+#[cfg(feature = "stats")]
+use std::time::Instant;
 
-/// The Discord character limit for a message
-pub const MSG_LIMIT: usize = 2000;
+pub mod limits;
+#[cfg(feature = "pulldown-cmark")]
+mod markdown;
 
-#[derive(Debug, Default, Clone)]
-/// A collection of strings which are all within the characters limit
-pub struct MsgBunch {
-    messages: Vec<String>,
+pub use limits::MSG_LIMIT;
+use limits::MSG_LIMIT_NITRO;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+/// How the length of a message is measured
+///
+/// More variants can be added, also by features like `unicode-width`, so matching on it needs a wildcard arm.
+pub enum LengthMode {
+    /// Count characters (Unicode scalar values), which is what Discord does
+    Chars,
+    /// Count UTF-16 code units
+    Utf16,
+    /// Count UTF-8 bytes
+    Bytes,
+    #[cfg(feature = "unicode-width")]
+    /// Count the columns taken up in a monospace font, with East Asian wide characters taking up two
+    ///
+    /// This is for aligning text in code blocks, not for the limits of a platform.
+    DisplayWidth,
 }
 
-impl MsgBunch {
-    fn new() -> Self {
-        MsgBunch {
-            messages: vec![String::with_capacity(MSG_LIMIT)]
+impl Default for LengthMode {
+    #[inline(always)]
+    fn default() -> Self {
+        LengthMode::Chars
+    }
+}
+
+impl LengthMode {
+    #[inline]
+    /// The length of a single character
+    pub fn char_len(self, c: char) -> usize {
+        match self {
+            LengthMode::Chars => 1,
+            LengthMode::Utf16 => c.len_utf16(),
+            LengthMode::Bytes => c.len_utf8(),
+            #[cfg(feature = "unicode-width")]
+            LengthMode::DisplayWidth => unicode_width::UnicodeWidthChar::width(c).unwrap_or(0),
         }
     }
 
-    /// Shorthand for `MsgBunchBuilder::new()`
-    #[inline(always)]
-    pub fn builder() -> MsgBunchBuilder {
-        MsgBunchBuilder::new()
+    /// The length of a string
+    pub fn len(self, s: &str) -> usize {
+        match self {
+            LengthMode::Chars => s.chars().count(),
+            LengthMode::Utf16 => s.encode_utf16().count(),
+            LengthMode::Bytes => s.len(),
+            #[cfg(feature = "unicode-width")]
+            LengthMode::DisplayWidth => s.chars().map(|c| self.char_len(c)).sum(),
+        }
     }
 
-    /// Consumes the `MsgBunch` and returns the inner vector of strings
-    pub fn into_inner(self) -> Vec<String> {
-        self.messages
+    /// The byte index at which `s` should be cut for the first part to be at most `max` long
+    ///
+    /// The first part always contains at least one character, if `s` isn't empty, so splitting always makes progress.
+    fn split_index(self, s: &str, max: usize) -> usize {
+        let mut len = 0;
+        for (i, c) in s.char_indices() {
+            len += self.char_len(c);
+            if len > max {
+                return if i == 0 { c.len_utf8() } else { i };
+            }
+        }
+        s.len()
+    }
+
+    /// Splits `text` into pieces of at most `limit` length with no regard for where
+    fn hard_split(self, text: &str, limit: usize) -> Vec<String> {
+        let mut messages = Vec::new();
+        let mut rest = text;
+        while self.len(rest) > limit {
+            let (msg, new_rest) = rest.split_at(self.split_index(rest, limit));
+            messages.push(msg.to_owned());
+            rest = new_rest;
+        }
+        messages.push(rest.to_owned());
+        messages
     }
 }
 
-impl IntoIterator for MsgBunch {
-    type IntoIter = VecIntoIter<String>;
-    type Item = String;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Chat platforms with known message limits
+pub enum Platform {
+    /// Discord, 2000 characters
+    Discord,
+    /// Discord with Nitro, 4000 characters
+    DiscordNitro,
+    /// Slack, 4000 bytes
+    Slack,
+    /// Telegram, 4096 UTF-16 code units
+    Telegram,
+    /// Twitch chat, 500 characters
+    Twitch,
+}
 
+impl Default for Platform {
     #[inline(always)]
-    fn into_iter(self) -> Self::IntoIter {
-        self.messages.into_iter()
+    fn default() -> Self {
+        Platform::Discord
     }
 }
 
-#[derive(Debug)]
-/// Structure for making an `MsgBunch` that allows control over where the messsage can be split
-pub struct MsgBunchBuilder {
-    /// the inner `MsgBunch` being worked on
-    /// will not contain the current split section
-    /// use `build` to make sure you get the full thing
-    pub inner: MsgBunch,
-    chars_num: usize, 
-    no_split_section: Option<(String, usize)>,
+impl Platform {
+    /// The maximum length of a message on the platform, measured with `Platform::length_mode`
+    pub fn limit(self) -> usize {
+        match self {
+            Platform::Discord => MSG_LIMIT,
+            Platform::DiscordNitro => MSG_LIMIT_NITRO,
+            Platform::Slack => 4000,
+            Platform::Telegram => 4096,
+            Platform::Twitch => 500,
+        }
+    }
+
+    /// How the platform measures the length of a message
+    pub fn length_mode(self) -> LengthMode {
+        match self {
+            Platform::Discord | Platform::DiscordNitro | Platform::Twitch => LengthMode::Chars,
+            Platform::Slack => LengthMode::Bytes,
+            Platform::Telegram => LengthMode::Utf16,
+        }
+    }
 }
 
-impl Default for MsgBunchBuilder {
-    #[inline(always)]
+#[derive(Debug, Clone)]
+/// A collection of strings which are all within the characters limit
+pub struct MsgBunch {
+    messages: Vec<String>,
+    limit: usize,
+    length_mode: LengthMode,
+    truncated: bool,
+    omitted: Vec<String>,
+    sections: Vec<SectionPlacement>,
+    /// the indices of the messages that continue content which was hard split
+    hard_split_before: Vec<usize>,
+    /// the index of the message each named section starts in
+    named_sections: HashMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The sizes of the messages in a `MsgBunch`, see `MsgBunch::size_stats`
+pub struct SizeStats {
+    /// The length of the shortest message
+    pub min: usize,
+    /// The length of the longest message
+    pub max: usize,
+    /// The average length of the messages
+    pub mean: f64,
+    /// How many messages there are
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How content was split between two messages
+pub enum SplitKind {
+    /// At a nice point, like between sections, after a character allowed to split at or at a message break
+    Nice,
+    /// Somewhere that isn't nice, because there was nowhere better
+    Hard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Where a message starts in the joined messages, see `MsgBunch::into_source`
+pub struct SplitPoint {
+    /// The byte offset at which the message starts
+    pub offset: usize,
+    /// How the content was split there
+    pub kind: SplitKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Where a section ended up, see `MsgBunchBuilder::record_sections`
+pub struct SectionPlacement {
+    /// The index of the section, counting every ended section
+    pub section_index: usize,
+    /// The index of the message the section starts in
+    pub message_index: usize,
+    /// Whether the section had to be split over several messages
+    pub was_split: bool,
+    /// How many times the section was split
+    pub split_count: usize,
+}
+
+impl Default for MsgBunch {
+    #[inline]
     fn default() -> Self {
-        MsgBunchBuilder::new()
+        MsgBunch::from_messages(Vec::new(), MSG_LIMIT, LengthMode::Chars)
     }
 }
 
-impl MsgBunchBuilder {
-    #[inline]
-    /// Begin making an `MsgBunch`
-    pub fn new() -> Self {
-        MsgBunchBuilder {
-            inner: MsgBunch::new(),
-            chars_num: 0,
-            no_split_section: None,
+impl MsgBunch {
+    fn new(limit: usize, length_mode: LengthMode) -> Self {
+        MsgBunch::from_messages(vec![String::with_capacity(limit)], limit, length_mode)
+    }
+
+    /// A bunch of `messages` with nothing recorded about how they were split
+    fn from_messages(messages: Vec<String>, limit: usize, length_mode: LengthMode) -> Self {
+        MsgBunch {
+            messages,
+            limit,
+            length_mode,
+            truncated: false,
+            omitted: Vec::new(),
+            sections: Vec::new(),
+            hard_split_before: Vec::new(),
+            named_sections: HashMap::new(),
         }
     }
 
-    /// Adds a string to the `MsgBunch` splitting if necessary
-    /// This changes the way it splits depending on whether it is currently in a section.
+    /// Shorthand for `MsgBunchBuilder::new()`
+    #[inline(always)]
+    pub fn builder() -> MsgBunchBuilder {
+        MsgBunchBuilder::new()
+    }
+
+    /// How many messages `dense` would split `text` into for each of the limits
     ///
-    /// Sections are started with `MsgBunchBuilder::begin_section` and ended with `MsgBunchBuilder::end_section`
+    /// That's the fewest messages `text` fits in, so it's a lower bound on how many a `MsgBunchBuilder` makes,
+    /// which splits at nicer places and may need more.
+    /// The text is only counted once, so this is cheaper than splitting it for every limit.
     ///
-    /// # Example
+    /// # Panics
     ///
-    /// For example you tell the builder to try not to split a welcome message.
-    /// It will then try its best not to split in the middle of that section, putting it into its own message if need be.
+    /// Panics if any of the limits is 0
+    pub fn count_for_limits(text: &str, limits: &[usize]) -> Vec<usize> {
+        let len = text.chars().count();
+        limits.iter().map(|&limit| {
+            assert!(limit > 0, "message limit must be positive");
+            len.div_ceil(limit).max(1)
+        }).collect()
+    }
+
+    /// Packs `text` into messages of exactly `limit` characters each (except for the last one)
     ///
-    /// ```
-    /// # fn get_name() -> String { "Jens".to_owned() }
-    /// # fn motd() -> &'static str {""}
-    /// use discord_utils::MsgBunchBuilder;
-    /// 
-    /// let mut mmb = MsgBunchBuilder::new();
-    /// mmb
-    ///     .begin_section()
-    ///     .add_string("Hello, ")
-    ///     // Some sort of function that gets the name of the user
-    ///     .add_string(get_name())
-    ///     .add_string("!\n")
-    ///     .end_section()
-    ///     // Perhaps the message ends with an motd, that contains a lot of lines.
-    ///     .add_lines(motd());
-    /// 
-    /// let msg_bunch = mmb.build();
-    /// ```
-    pub fn add_string<S: AsRef<str>>(&mut self, s: S) -> &mut Self {
-        let string_to_add = s.as_ref();
-        let string_to_add_size = string_to_add.chars().count();
+    /// This ignores sections and nice split points entirely and only does hard splits,
+    /// giving the fewest possible messages at the cost of readability.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is 0
+    pub fn dense(text: &str, limit: usize) -> MsgBunch {
+        assert!(limit > 0, "message limit must be positive");
 
-        if let Some((no_split_section, size)) = &mut self.no_split_section {
-            *size += string_to_add_size;
-            no_split_section.push_str(string_to_add);
-        } else if self.chars_num + string_to_add_size > MSG_LIMIT {
-            let cur_msg = self.inner.messages.last_mut().unwrap();
-            let cur_msg_size = cur_msg.chars().count();
+        let mut bunch = MsgBunch::from_messages(Vec::new(), limit, LengthMode::Chars);
+        bunch.push_hard_split(text);
+        bunch
+    }
 
-            let (s, index) = (cur_msg_size+1..).zip(string_to_add.char_indices()).map(|(s, (i, _))| (s, i)).nth(MSG_LIMIT-cur_msg_size).unwrap();
-            debug_assert_eq!(s, MSG_LIMIT);
+    /// Counts how many messages `text` needs when measured in UTF-16 code units against `MSG_LIMIT`
+    ///
+    /// Characters outside the Basic Multilingual Plane, like most emoji, count as two.
+    pub fn count_utf16(text: &str) -> usize {
+        let mut mbb = MsgBunchBuilder::with_limit_and_mode(MSG_LIMIT, LengthMode::Utf16);
+        mbb.add_string(text);
+        mbb.build().messages.iter().filter(|msg| !msg.is_empty()).count()
+    }
 
-            cur_msg.push_str(&string_to_add[..index]);
+    #[inline]
+    /// Packs whole lines of `text` into messages of at most `limit` characters
+    ///
+    /// A message boundary only ever falls between lines, except for lines that are longer than `limit` by themselves.
+    /// Those get their own messages and are hard split, which means that e.g. a line of JSON will no longer be valid JSON in either message.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is 0
+    pub fn split_on_lines_only(text: &str, limit: usize) -> MsgBunch {
+        MsgBunch::from_delimited_keep(text, "\n", limit)
+    }
 
-            let new_cur_msg = string_to_add[index..].to_owned();
-            let new_cur_msg_size = new_cur_msg.chars().count();
+    /// Packs the pieces of `text` ending with `delim` into messages of at most `limit` characters
+    ///
+    /// The delimiter stays at the end of the piece before it, so joining the messages gives back `text`.
+    /// A message boundary only ever falls right after a delimiter, except for pieces that are longer than `limit` by themselves,
+    /// which are hard split like in `split_on_lines_only`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `delim` is empty or `limit` is 0
+    pub fn from_delimited_keep(text: &str, delim: &str, limit: usize) -> MsgBunch {
+        assert!(!delim.is_empty(), "delimiter must not be empty");
+        assert!(limit > 0, "message limit must be positive");
 
-            self.inner.messages.push(string_to_add[index..].to_owned());
-            self.chars_num = new_cur_msg_size;
-        } else {
-            self.inner.messages.last_mut().unwrap().push_str(string_to_add);
-            self.chars_num += string_to_add_size;
+        let mode = LengthMode::Chars;
+        let mut bunch = MsgBunch::from_messages(Vec::new(), limit, mode);
+        let mut cur_msg = String::new();
+        let mut cur_msg_size = 0;
+
+        for piece in text.split_inclusive(delim) {
+            let piece_size = mode.len(piece);
+            if cur_msg_size + piece_size > limit && cur_msg_size > 0 {
+                bunch.messages.push(take(&mut cur_msg));
+                cur_msg_size = 0;
+            }
+            if piece_size > limit {
+                // the last part is continued like any other piece, at the index it was split before
+                bunch.push_hard_split(piece);
+                cur_msg = bunch.messages.pop().unwrap();
+                cur_msg_size = mode.len(&cur_msg);
+            } else {
+                cur_msg.push_str(piece);
+                cur_msg_size += piece_size;
+            }
         }
-        self
+        bunch.messages.push(cur_msg);
+
+        bunch
     }
 
-    /// Begins a section which affects subsequent calls to `add_string`
-    /// 
-    /// Does nothing if a section is already in progress
-    pub fn begin_section(&mut self) -> &mut Self {
-        if self.no_split_section.is_none() {
-            self.no_split_section = Some((String::new(), 0));
+    /// Puts `sentences_per_message` sentences of `text` in each message, splitting them further only to keep within `limit` characters
+    ///
+    /// A sentence ends at a `.`, `?` or `!` followed by whitespace. The whitespace between groups of sentences is left out.
+    /// A group that's too long is split after whitespace, or anywhere if there's none, see `MsgBunchBuilder::prefer_blank_line_breaks`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sentences_per_message` or `limit` is 0
+    pub fn chunk_sentences(text: &str, sentences_per_message: usize, limit: usize) -> MsgBunch {
+        assert!(sentences_per_message > 0, "sentences per message must be positive");
+
+        let mut mbb = MsgBunchBuilder::with_limit(limit);
+        mbb.prefer_blank_line_breaks(true);
+        let mut group_start = 0;
+        let mut sentences = 0;
+        let mut chars = text.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let ends_sentence = matches!(c, '.' | '?' | '!') && chars.peek().map_or(true, |&(_, next)| next.is_whitespace());
+            if ends_sentence {
+                sentences += 1;
+                if sentences == sentences_per_message {
+                    let group_end = i + c.len_utf8();
+                    mbb.add_string(text[group_start..group_end].trim()).break_message();
+                    group_start = group_end;
+                    sentences = 0;
+                }
+            }
         }
-        self
+        let rest = text[group_start..].trim();
+        if !rest.is_empty() {
+            mbb.add_string(rest);
+        }
+        mbb.build()
     }
 
-    #[inline]
-    /// Whether we are in a section right now.
-    pub fn is_in_section(&self) -> bool {
-        self.no_split_section.is_some()
+    /// Puts the contents of a file in code blocks of at most `limit` characters, after a header like `📄 main.rs`
+    ///
+    /// The language of the code blocks is taken from the file extension of `path_or_name`,
+    /// which a name like `.bashrc` doesn't have.
+    /// The contents are split between lines, see `MsgBunchBuilder::add_code_block_lines`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is 0
+    pub fn code_file(path_or_name: &str, contents: &str, limit: usize) -> MsgBunch {
+        let name = path_or_name.rsplit(['/', '\\']).next().unwrap_or(path_or_name);
+        let extension = name.rsplit_once('.').filter(|(stem, _)| !stem.is_empty()).map_or("", |(_, extension)| extension).to_lowercase();
+        let lang = match &*extension {
+            "yml" => "yaml",
+            "htm" => "html",
+            "h" => "c",
+            "hpp" | "cc" | "cxx" => "cpp",
+            "md" => "markdown",
+            _ => &extension,
+        };
+
+        let mut mbb = MsgBunchBuilder::with_limit(limit);
+        mbb.add_string(format!("📄 {}\n", name)).add_code_block_lines(lang, contents.lines());
+        mbb.build()
     }
 
     #[inline]
-    /// Ends a section which affects subsequent calls to `add_string`
-    /// 
-    /// If the section is over the `MSG_LIMIT` it will try to split at a nice point, see `end_section_with`
-    /// 
-    /// Does nothing if no section is in progress
-    pub fn end_section(&mut self) -> &mut Self {
-        self.end_section_with(|c| match c {
-            ';' | ',' | '.' | '?' | '!' | ')' | ':' | '-' => true,
-            _ => false
-        })
+    /// Alternates between the messages of `a` and `b`, e.g. for a transcript of two speakers
+    ///
+    /// See `interleave_labeled` for details.
+    pub fn interleave(a: MsgBunch, b: MsgBunch) -> MsgBunch {
+        MsgBunch::interleave_labeled(a, b, "", "")
     }
 
-    /// Ends a section which affects subsequent calls to `add_string`
-    /// 
-    /// If the section is over the `MSG_LIMIT` it will try to split at a nice point defined by the provided callback.
-    /// The callback is used to find characters that are appropriate to split at.
-    /// It go travel backwards from the split point, calling the callback until it returns true.
-    /// 
-    /// Does nothing if no section is in progress
-    pub fn end_section_with<F: FnMut(char) -> bool>(&mut self, mut f: F) -> &mut Self {
-        if let Some((mut no_split_section, size)) = self.no_split_section.take() {
-            if self.chars_num + size > MSG_LIMIT {
-                self.chars_num = size;
-
-                let mut no_split_section_size = no_split_section.chars().count();
+    /// Alternates between the messages of `a` and `b`, putting `label_a` or `label_b` at the start of each message
+    ///
+    /// The result uses the limit and length mode of `a`.
+    /// Messages that would go over it because of the label or a bigger limit in `b` are split again, each part getting the label.
+    /// A label that doesn't leave room for any content is cut short until it does.
+    /// When one bunch runs out of messages, the rest of the other one follows.
+    pub fn interleave_labeled(a: MsgBunch, b: MsgBunch, label_a: &str, label_b: &str) -> MsgBunch {
+        let (limit, length_mode) = (a.limit, a.length_mode);
+        let shorten = |label: &str| {
+            let mut label = label.to_owned();
+            while length_mode.len(&label) >= limit && label.pop().is_some() {}
+            label
+        };
+        let (label_a, label_b) = (&*shorten(label_a), &*shorten(label_b));
+        let mut bunch = MsgBunch::from_messages(Vec::with_capacity(a.messages.len() + b.messages.len()), limit, length_mode);
 
-                // If the section is longer than the msg limit, we have to split it anyway
-                // using the passed function to check charactes that should allow splits
-                while no_split_section_size > MSG_LIMIT {
-                    // take(MSG_LIMIT) so that it'll panic if it doesn't find something to split at before message limit
-                    let (mut index, _) = no_split_section.char_indices().rev().skip(no_split_section_size-MSG_LIMIT).take(MSG_LIMIT).find(|(_, c)| f(*c)).unwrap();
-                    index += 1;
+        let mut add = |msg: String, label: &str| {
+            if msg.is_empty() {
+                return;
+            }
+            let mut mbb = MsgBunchBuilder::with_limit_and_mode(limit, length_mode);
+            mbb.sticky_prefix(label).add_string(msg);
+            let built = mbb.build();
+            let offset = bunch.messages.len();
+            bunch.hard_split_before.extend(built.hard_split_before.iter().map(|i| offset + i));
+            bunch.messages.extend(built.messages);
+        };
 
-                    while !no_split_section.is_char_boundary(index) {
-                        index += 1;
+        let mut a = a.messages.into_iter();
+        let mut b = b.messages.into_iter();
+        loop {
+            match (a.next(), b.next()) {
+                (None, None) => break,
+                (msg_a, msg_b) => {
+                    if let Some(msg) = msg_a {
+                        add(msg, label_a);
+                    }
+                    if let Some(msg) = msg_b {
+                        add(msg, label_b);
                     }
+                }
+            }
+        }
 
-                    let new_cur_msg = no_split_section.split_off(index);
+        bunch
+    }
 
-                    let first_section = replace(&mut no_split_section, new_cur_msg);
-                    no_split_section_size = no_split_section.chars().count();
+    /// Hard splits `text` at the limit and adds the parts as messages, recording the splits between them
+    fn push_hard_split(&mut self, text: &str) {
+        for (i, msg) in self.length_mode.hard_split(text, self.limit).into_iter().enumerate() {
+            if i > 0 {
+                self.hard_split_before.push(self.messages.len());
+            }
+            self.messages.push(msg);
+        }
+    }
 
-                    self.inner.messages.push(first_section);
+    /// Updates what's recorded about the messages after they were merged or removed,
+    /// with `new_index` giving the index each message ended up at, if it's still there
+    ///
+    /// A split is only kept if the messages on both sides of it are kept apart,
+    /// and a section placement only if every message of the section is kept.
+    fn remap_indices(&mut self, new_index: &[Option<usize>]) {
+        let new_index = |i: usize| new_index.get(i).copied().flatten();
+        self.hard_split_before.retain_mut(|i| {
+            debug_assert!(*i > 0, "there's no split before the first message");
+            match (new_index(*i - 1), new_index(*i)) {
+                (Some(prev), Some(cur)) if prev != cur => {
+                    *i = cur;
+                    true
                 }
-                self.inner.messages.push(no_split_section);
-            } else {
-                self.chars_num += size;
-                self.inner.messages.last_mut().unwrap().push_str(&no_split_section)
+                _ => false,
             }
-        }
-        self
+        });
+        self.sections.retain_mut(|placement| {
+            match (new_index(placement.message_index), new_index(placement.message_index + placement.split_count)) {
+                (Some(first), Some(last)) => {
+                    placement.message_index = first;
+                    placement.split_count = last - first;
+                    placement.was_split = last > first;
+                    true
+                }
+                _ => false,
+            }
+        });
+        self.named_sections.retain(|_, index| new_index(*index).map(|i| *index = i).is_some());
     }
 
-    /// Add lines with each line being a separate section
-    pub fn add_lines<S: AsRef<str>>(&mut self, lines: S) -> &mut Self {
-        for line in lines.as_ref().lines() {
-            self.begin_section().add_string(line).add_string("\n").end_section();
+    #[inline]
+    /// The limit the messages were made to fit within
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    #[inline]
+    /// How the length of the messages is measured
+    pub fn length_mode(&self) -> LengthMode {
+        self.length_mode
+    }
+
+    #[inline]
+    /// Iterates over the messages
+    pub fn iter(&self) -> std::slice::Iter<'_, String> {
+        self.messages.iter()
+    }
+
+    #[inline]
+    /// Iterates mutably over the messages
+    ///
+    /// Use `normalize` afterwards if the edits could have made messages go over the limit.
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, String> {
+        self.messages.iter_mut()
+    }
+
+    /// Joins all messages and splits them again at the limit of the `MsgBunch`
+    ///
+    /// This restores the guarantee that every message is within the limit, e.g. after editing messages with `iter_mut`.
+    /// Only hard splits are made, like with `MsgBunch::dense`.
+    /// Where sections were is forgotten, so there are no section placements or named sections afterwards.
+    pub fn normalize(&mut self) {
+        let text = self.messages.concat();
+        self.messages.clear();
+        self.hard_split_before.clear();
+        self.sections.clear();
+        self.named_sections.clear();
+        self.push_hard_split(&text);
+    }
+
+    /// Merges the last message into the one before it if it's shorter than `threshold` and they fit together within the limit
+    pub fn absorb_short_tail(&mut self, threshold: usize) {
+        if let [.., prev, last] = &mut self.messages[..] {
+            let last_len = self.length_mode.len(last);
+            if last_len < threshold && self.length_mode.len(prev) + last_len <= self.limit {
+                prev.push_str(last);
+                self.messages.pop();
+                let len = self.messages.len();
+                let new_index: Vec<_> = (0..len).chain(Some(len - 1)).map(Some).collect();
+                self.remap_indices(&new_index);
+            }
         }
+    }
 
-        self
+    #[inline]
+    /// The words that were replaced because of `OversizedToken::Replace`
+    pub fn omitted(&self) -> &[String] {
+        &self.omitted
     }
 
     #[inline]
-    /// Finalise the current section if one is in progress
-    /// and return the final `MsgBunch`
-    pub fn build(mut self) -> MsgBunch {
-        self.end_section();
-        self.inner
+    /// Where each section ended up, if the builder was told to record it with `MsgBunchBuilder::record_sections`
+    pub fn section_placements(&self) -> &[SectionPlacement] {
+        &self.sections
     }
-}
 
-/// Splits a string into front trim text and end_trim
-/// 
-/// If the string only consists of whitespace, all but the end trim will be empty.
-/// If the string has no whitespace surrounding, the trim strings will be empty.
-pub fn split_trim(s: &str) -> (&str, &str, &str) {
-    let end_trim_index = s.rfind(|c: char| !c.is_whitespace()).map(|i| {
-        i + s[i..].chars().next().unwrap().len_utf8()
-    }).unwrap_or(0);
-    
-    let (start, end_trim) = s.split_at(end_trim_index);
-    
-    let front_trim_index = start.find(|c: char| !c.is_whitespace()).unwrap_or(end_trim_index);
+    #[inline]
+    /// The index of the message the section named `name` starts in, see `MsgBunchBuilder::begin_named_section`
+    pub fn section_message(&self, name: &str) -> Option<usize> {
+        self.named_sections.get(name).copied()
+    }
 
-    let (front_trim, text) = start.split_at(front_trim_index);
+    #[inline]
+    /// Whether content was cut off because of `OverflowPolicy::Truncate`
+    pub fn was_truncated(&self) -> bool {
+        self.truncated
+    }
 
-    (front_trim, text, end_trim)
-}
+    #[inline]
+    /// How many messages contain `needle`
+    ///
+    /// A `needle` split between two messages isn't counted, see `contains_across` for that.
+    pub fn count_containing(&self, needle: &str) -> usize {
+        self.messages.iter().filter(|msg| msg.contains(needle)).count()
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::split_trim;
-    #[test]
-    fn test_split_trim() {
-        assert_eq!(split_trim("hestetest"), ("", "hestetest", ""));
-        assert_eq!(split_trim("   hest  \n\n asdg \t\n"), ("   ", "hest  \n\n asdg", " \t\n"));
-        assert_eq!(split_trim("\n"), ("", "", "\n"));
-        assert_eq!(split_trim(" "), ("", "", " "));
+    /// Whether the messages joined together contain `needle`, even if it was split between messages
+    pub fn contains_across(&self, needle: &str) -> bool {
+        self.messages.concat().contains(needle)
+    }
+
+    #[inline]
+    /// How many UTF-8 bytes all the messages are together, which is the sum of `per_message_bytes`
+    pub fn total_bytes(&self) -> usize {
+        self.messages.iter().map(String::len).sum()
+    }
+
+    /// How many UTF-8 bytes each message is, leaving out empty messages
+    pub fn per_message_bytes(&self) -> Vec<usize> {
+        self.messages.iter().filter(|msg| !msg.is_empty()).map(String::len).collect()
+    }
+
+    /// How long sending all the messages takes if each one takes `per_message`
+    ///
+    /// Messages that are empty or only whitespace aren't counted, since they can't be sent.
+    pub fn estimated_send_duration(&self, per_message: Duration) -> Duration {
+        let sendable = self.messages.iter().filter(|msg| !msg.trim().is_empty()).count();
+        per_message * sendable as u32
     }
-}
\ No newline at end of file
+
+    #[cfg(feature = "futures")]
+    /// Sends every message in order by awaiting `f` for each, stopping at the first error
+    ///
+    /// Messages that are empty or only whitespace are skipped, since they can't be sent.
+    /// `f` can send with any Discord library, or be a mock in tests.
+    pub async fn send_with<F, Fut, E>(&self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&str) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+    {
+        for msg in self.messages.iter().filter(|msg| !msg.trim().is_empty()) {
+            f(msg).await?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    /// Removes the messages in `range` and returns them in an iterator, like `Vec::drain`
+    ///
+    /// E.g. `drain(..n)` takes the first `n` messages and keeps the rest in the bunch to be sent later.
+    pub fn drain<R: RangeBounds<usize>>(&mut self, range: R) -> std::vec::Drain<'_, String> {
+        let len = self.messages.len();
+        let start = match range.start_bound() {
+            Bound::Included(&i) => i,
+            Bound::Excluded(&i) => i + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&i) => i + 1,
+            Bound::Excluded(&i) => i,
+            Bound::Unbounded => len,
+        };
+        // an invalid range makes `Vec::drain` panic below
+        if start <= end {
+            let new_index: Vec<_> = (0..len).map(|i| match i {
+                i if i < start => Some(i),
+                i if i < end => None,
+                i => Some(i - (end - start)),
+            }).collect();
+            self.remap_indices(&new_index);
+        }
+        self.messages.drain(start..end)
+    }
+
+    /// Consumes the `MsgBunch` and returns the messages joined together and where each message after the first starts
+    ///
+    /// The messages can be made again from these with `from_source`.
+    pub fn into_source(self) -> (String, Vec<SplitPoint>) {
+        let mut source = String::with_capacity(self.messages.iter().map(String::len).sum());
+        let mut split_points = Vec::with_capacity(self.messages.len().saturating_sub(1));
+        for (i, msg) in self.messages.iter().enumerate() {
+            if i > 0 {
+                let kind = if self.hard_split_before.contains(&i) { SplitKind::Hard } else { SplitKind::Nice };
+                split_points.push(SplitPoint { offset: source.len(), kind });
+            }
+            source.push_str(msg);
+        }
+        (source, split_points)
+    }
+
+    /// Makes the messages returned by `into_source` again by splitting `source` at `split_points`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the offsets aren't in order or aren't at character boundaries in `source`
+    pub fn from_source(source: &str, split_points: &[SplitPoint], limit: usize, length_mode: LengthMode) -> MsgBunch {
+        let mut bunch = MsgBunch::from_messages(Vec::new(), limit, length_mode);
+        let mut start = 0;
+        for (i, point) in split_points.iter().enumerate() {
+            bunch.messages.push(source[start..point.offset].to_owned());
+            if point.kind == SplitKind::Hard {
+                bunch.hard_split_before.push(i + 1);
+            }
+            start = point.offset;
+        }
+        bunch.messages.push(source[start..].to_owned());
+        bunch
+    }
+
+    /// Consumes the `MsgBunch` and returns the inner vector of strings
+    pub fn into_inner(self) -> Vec<String> {
+        self.messages
+    }
+
+    /// Consumes the `MsgBunch` and splits it into the first message, to start a thread with, and the rest, to reply with
+    ///
+    /// Empty messages are left out, and the starter is empty if there are no messages.
+    /// If the starter has a smaller limit, build with `MsgBunchBuilder::first_message_limit`.
+    pub fn thread_mode(self) -> (String, Vec<String>) {
+        let mut messages = self.messages.into_iter().filter(|msg| !msg.is_empty());
+        let starter = messages.next().unwrap_or_default();
+        (starter, messages.collect())
+    }
+
+    /// Returns each message cut down to at most `per_message` characters,
+    /// with `ellipsis` appended to the ones that had to be cut
+    ///
+    /// This is only meant for displaying previews and leaves the `MsgBunch` untouched.
+    pub fn truncated_preview(&self, per_message: usize, ellipsis: &str) -> Vec<String> {
+        self.messages.iter().map(|msg| {
+            match msg.char_indices().nth(per_message) {
+                Some((index, _)) => {
+                    let mut preview = String::with_capacity(index + ellipsis.len());
+                    preview.push_str(&msg[..index]);
+                    preview.push_str(ellipsis);
+                    preview
+                }
+                None => msg.clone(),
+            }
+        }).collect()
+    }
+
+    /// A summary of at most `max_chars` characters, e.g. for notification previews
+    ///
+    /// This is the content without Markdown, as in `with_transcript`, with all whitespace collapsed into single spaces.
+    /// If it's longer than `max_chars` it's cut short and ends with `…`, which counts towards `max_chars`.
+    pub fn summary(&self, max_chars: usize) -> String {
+        let transcript = strip_markdown(&self.messages.concat());
+        let mut summary = transcript.split_whitespace().collect::<Vec<_>>().join(" ");
+        if summary.chars().count() > max_chars {
+            // leaves room for the ellipsis
+            let keep = summary.char_indices().nth(max_chars.saturating_sub(1)).map_or(0, |(i, _)| i);
+            summary.truncate(keep);
+            if max_chars > 0 {
+                summary.push('…');
+            }
+        }
+        summary
+    }
+
+    /// Returns the messages with a prefix and suffix put around each one, leaving the `MsgBunch` untouched
+    ///
+    /// Both functions are given the index of the message and the total amount of messages.
+    /// The decorations aren't accounted for in the limit.
+    pub fn to_decorated<P, S>(&self, mut prefix_fn: P, mut suffix_fn: S) -> Vec<String>
+    where
+        P: FnMut(usize, usize) -> String,
+        S: FnMut(usize, usize) -> String,
+    {
+        let total = self.messages.len();
+        self.messages.iter().enumerate().map(|(i, msg)| {
+            let prefix = prefix_fn(i, total);
+            let suffix = suffix_fn(i, total);
+            let mut decorated = String::with_capacity(prefix.len() + msg.len() + suffix.len());
+            decorated.push_str(&prefix);
+            decorated.push_str(msg);
+            decorated.push_str(&suffix);
+            decorated
+        }).collect()
+    }
+
+    /// Consumes the `MsgBunch` and returns the messages with a prefix and suffix put around each one
+    ///
+    /// `decorate` is given the index of the message and the total amount of messages and returns the prefix and suffix.
+    /// The messages are reused instead of copied. The decorations aren't accounted for in the limit.
+    pub fn into_decorated<F: FnMut(usize, usize) -> (String, String)>(self, mut decorate: F) -> Vec<String> {
+        let total = self.messages.len();
+        self.messages.into_iter().enumerate().map(|(i, mut msg)| {
+            let (prefix, suffix) = decorate(i, total);
+            msg.insert_str(0, &prefix);
+            msg.push_str(&suffix);
+            msg
+        }).collect()
+    }
+
+    /// Renders every message with its index and character count, one per line, like `[0] (1998 chars): "..."`
+    ///
+    /// The content is escaped like with `{:?}` so each message stays on one line.
+    /// Meant for tests and bug reports about unexpected splits.
+    pub fn debug_layout(&self) -> String {
+        let mut layout = String::new();
+        for (i, msg) in self.messages.iter().enumerate() {
+            layout.push_str(&format!("[{}] ({} chars): {:?}\n", i, msg.chars().count(), msg));
+        }
+        layout
+    }
+
+    /// The character offset at which each message starts if all the messages were joined
+    ///
+    /// Offsets are into the messages as they are, so anything the builder put into them,
+    /// like a sticky prefix, is counted too and the offsets won't line up with the text originally added.
+    pub fn message_offsets(&self) -> Vec<usize> {
+        let mut offset = 0;
+        self.messages.iter().map(|msg| {
+            let start = offset;
+            offset += msg.chars().count();
+            start
+        }).collect()
+    }
+
+    /// The index of the message containing the character at `offset` in the joined messages, see `message_offsets`
+    ///
+    /// Returns `None` if `offset` is past the end.
+    pub fn message_index_at(&self, offset: usize) -> Option<usize> {
+        let total: usize = self.messages.iter().map(|msg| msg.chars().count()).sum();
+        if offset >= total {
+            return None;
+        }
+        Some(self.message_offsets().partition_point(|&start| start <= offset) - 1)
+    }
+
+    #[cfg(feature = "serde")]
+    /// The messages as a JSON array of strings, leaving out empty messages, e.g. for webhook relays
+    pub fn to_json_array(&self) -> serde_json::Value {
+        self.messages.iter().filter(|msg| !msg.is_empty()).map(|msg| serde_json::Value::from(msg.as_str())).collect()
+    }
+
+    #[cfg(feature = "serde")]
+    /// Splits the content again to fit in embed fields and returns them as field objects for the Discord API
+    ///
+    /// The joined messages are split between lines to fit in `limits::EMBED_FIELD_VALUE_LIMIT` characters,
+    /// see `split_on_lines_only`, and `name_fn` is given the index of each field to name it, e.g. `|i| format!("Part {}", i + 1)`.
+    /// Names are cut short to `limits::EMBED_FIELD_NAME_LIMIT` characters.
+    /// An embed can only have `limits::EMBED_FIELDS_LIMIT` fields, so more than that many have to be spread over several embeds.
+    pub fn to_embed_fields<F: FnMut(usize) -> String>(&self, mut name_fn: F) -> Vec<serde_json::Value> {
+        let bunch = MsgBunch::split_on_lines_only(&self.messages.concat(), limits::EMBED_FIELD_VALUE_LIMIT);
+        bunch.messages.into_iter()
+            .filter(|value| !value.is_empty())
+            .enumerate()
+            .map(|(i, value)| {
+                let mut name = name_fn(i);
+                if let Some((end, _)) = name.char_indices().nth(limits::EMBED_FIELD_NAME_LIMIT) {
+                    name.truncate(end);
+                }
+                serde_json::json!({
+                    "name": name,
+                    "value": value,
+                    "inline": false,
+                })
+            })
+            .collect()
+    }
+
+    /// The shortest, longest and average length of the messages, measured with the length mode of the bunch
+    ///
+    /// Empty messages aren't counted. Without any messages, everything is 0.
+    pub fn size_stats(&self) -> SizeStats {
+        let sizes: Vec<_> = self.messages.iter().filter(|msg| !msg.is_empty()).map(|msg| self.length_mode.len(msg)).collect();
+        let count = sizes.len();
+        SizeStats {
+            min: sizes.iter().copied().min().unwrap_or(0),
+            max: sizes.iter().copied().max().unwrap_or(0),
+            mean: if count == 0 { 0. } else { sizes.iter().sum::<usize>() as f64 / count as f64 },
+            count,
+        }
+    }
+
+    /// A hash of the joined messages, to cheaply tell whether regenerated content differs from what was sent before
+    ///
+    /// The hash is 64-bit FNV-1a over the UTF-8 bytes, so it's the same across runs, platforms and versions of this crate.
+    /// How the content is split into messages doesn't affect it.
+    pub fn content_hash(&self) -> u64 {
+        self.messages.iter().fold(FNV_OFFSET_BASIS, |hash, msg| fnv1a(hash, msg.as_bytes()))
+    }
+
+    /// A hash of each message, to tell which messages differ from what was sent before and only edit those
+    ///
+    /// Each hash is 64-bit FNV-1a over the UTF-8 bytes of the message, like `content_hash`, so they're the same across runs.
+    pub fn message_keys(&self) -> Vec<u64> {
+        self.messages.iter().map(|msg| fnv1a(FNV_OFFSET_BASIS, msg.as_bytes())).collect()
+    }
+
+    /// The indices of the messages that differ from those in `previous`, e.g. to know which messages to edit
+    ///
+    /// If one bunch has more messages than the other, the indices of the extra messages are included too.
+    pub fn changed_indices(&self, previous: &MsgBunch) -> Vec<usize> {
+        let len = self.messages.len().max(previous.messages.len());
+        (0..len).filter(|&i| self.messages.get(i) != previous.messages.get(i)).collect()
+    }
+
+    /// Merges adjacent messages, as long as they stay within the limit, until there are at most `max` messages
+    ///
+    /// Messages are merged from the start and only as many as needed.
+    /// If it can't get down to `max` messages, nothing is merged and the fewest messages it could get down to is returned.
+    pub fn coalesce_to(&mut self, max: usize) -> Result<(), usize> {
+        let mode = self.length_mode;
+        let mut fewest = 0;
+        let mut cur_size = None;
+        for size in self.messages.iter().map(|msg| mode.len(msg)) {
+            match cur_size {
+                Some(cur) if cur + size <= self.limit => cur_size = Some(cur + size),
+                _ => {
+                    fewest += 1;
+                    cur_size = Some(size);
+                }
+            }
+        }
+        if fewest > max {
+            return Err(fewest);
+        }
+
+        let mut count = self.messages.len();
+        let mut merged: Vec<String> = Vec::with_capacity(max);
+        let mut new_index = Vec::with_capacity(count);
+        for msg in take(&mut self.messages) {
+            if let Some(last) = merged.last_mut() {
+                if count > max && mode.len(last) + mode.len(&msg) <= self.limit {
+                    last.push_str(&msg);
+                    count -= 1;
+                    new_index.push(Some(merged.len() - 1));
+                    continue;
+                }
+            }
+            merged.push(msg);
+            new_index.push(Some(merged.len() - 1));
+        }
+        self.messages = merged;
+        self.remap_indices(&new_index);
+        Ok(())
+    }
+
+    /// The messages along with a plain text transcript of them, e.g. for a search index
+    ///
+    /// The transcript is the messages joined with their Markdown removed:
+    /// - the fence lines of code blocks and the backticks around inline code, but not what's in them
+    /// - `**`, `*`, `__`, `~~` and `||` anywhere but `*` list markers, and `_` that isn't in the middle of a word
+    /// - `#`, `##` or `###` headings, `>` and `>>>` quotes and `-#` subtext at the start of lines
+    /// - backslashes escaping punctuation, leaving the punctuation
+    /// - the brackets of masked links, `[text](url)` becoming `text (url)`, and the angle brackets around `<url>`
+    ///
+    /// List markers and Discord tokens like mentions are left as they are.
+    pub fn with_transcript(&self) -> (&[String], String) {
+        (&self.messages, strip_markdown(&self.messages.concat()))
+    }
+
+    /// Joins the messages together without the lines repeated by `MsgBunchBuilder::overlap_lines`
+    ///
+    /// `overlap` should be the amount of lines that were repeated. Lines at the start of a message are only left out
+    /// if they are quoted copies of the last lines of the message before it.
+    pub fn join_dedup_overlap(&self, overlap: usize) -> String {
+        let mut joined = String::new();
+        let mut prev_lines: Vec<&str> = Vec::new();
+        for msg in &self.messages {
+            let mut lines: Vec<&str> = msg.split_inclusive('\n').collect();
+            let is_copy = |(line, prev): (&&str, &&str)| {
+                line.strip_prefix(OVERLAP_MARKER).is_some_and(|line| line.trim_end_matches('\n') == prev.trim_end_matches('\n'))
+            };
+            let repeated = (1..=overlap.min(lines.len()).min(prev_lines.len()))
+                .rev()
+                .find(|&n| lines[..n].iter().zip(&prev_lines[prev_lines.len() - n..]).all(is_copy))
+                .unwrap_or(0);
+            for line in &lines[repeated..] {
+                joined.push_str(line);
+            }
+            prev_lines = lines.split_off(repeated);
+        }
+        joined
+    }
+
+    /// Joins messages back together where they were split in the middle of something
+    ///
+    /// A split is considered to be in the middle of something when the earlier message doesn't end with whitespace
+    /// or one of the characters `end_section` considers nice to split after.
+    pub fn logical_segments(&self) -> Vec<String> {
+        let mut segments: Vec<String> = Vec::new();
+        let mut joins_previous = false;
+        for msg in &self.messages {
+            match segments.last_mut() {
+                Some(segment) if joins_previous => segment.push_str(msg),
+                _ => segments.push(msg.clone()),
+            }
+            joins_previous = msg.chars().next_back().is_some_and(|c| !c.is_whitespace() && !is_nice_split(c));
+        }
+        segments
+    }
+
+    /// Counts the fenced code blocks across all messages by their language tag
+    ///
+    /// Code blocks without a language tag are counted under `""`.
+    /// Messages are joined before scanning, so a block split over several messages is counted once.
+    pub fn code_block_stats(&self) -> HashMap<String, usize> {
+        let mut stats = HashMap::new();
+        for lang in code_block_languages(&self.messages.concat()) {
+            *stats.entry(lang.to_owned()).or_insert(0) += 1;
+        }
+        stats
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Continues a 64-bit FNV-1a hash with `bytes`, start with `FNV_OFFSET_BASIS`
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME))
+}
+
+/// Formats `n` with commas between every group of thousands, like `12,345`
+fn group_thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Roughly how many columns `c` takes up in a monospace font
+///
+/// Combining marks and zero width characters take up none and wide characters from East Asian scripts and emoji take up two.
+fn approx_display_width(c: char) -> usize {
+    #[cfg(feature = "unicode-width")]
+    {
+        LengthMode::DisplayWidth.char_len(c)
+    }
+    #[cfg(not(feature = "unicode-width"))]
+    match c as u32 {
+        0x0300..=0x036F | 0x200B..=0x200F | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Collapses runs of spaces and tabs into one space and removes them at the start and end of lines,
+/// leaving inline code alone
+///
+/// `after_space` tells whether what comes before `s` ends a line or with a space, so `s` shouldn't start with one.
+/// Spaces at the end of `s` become one space, since the line might go on.
+fn normalize_whitespace(s: &str, after_space: bool) -> String {
+    let mut at_line_start = after_space;
+    let mut out = String::with_capacity(s.len());
+    let mut pending_space = false;
+    let mut in_inline_code = false;
+    for c in s.chars() {
+        if in_inline_code {
+            out.push(c);
+            in_inline_code = c != '`';
+            continue;
+        }
+        match c {
+            ' ' | '\t' => pending_space = true,
+            '\n' => {
+                out.push('\n');
+                pending_space = false;
+                at_line_start = true;
+            }
+            _ => {
+                if pending_space && !at_line_start {
+                    out.push(' ');
+                }
+                out.push(c);
+                pending_space = false;
+                at_line_start = false;
+                in_inline_code = c == '`';
+            }
+        }
+    }
+    if pending_space && !at_line_start {
+        out.push(' ');
+    }
+    out
+}
+
+/// Puts every URL in `s` on its own line by replacing the spaces around them with newlines
+///
+/// `at_line_start` tells whether what comes before `s` ends a line.
+/// `url_before` tells whether that's because it ends with a URL that already got its line break,
+/// and is set to whether `s` ends with one.
+fn urls_on_own_lines<'a>(s: &'a str, mut at_line_start: bool, url_before: &mut bool) -> Cow<'a, str> {
+    let is_url = |word: &str| word.starts_with("https://") || word.starts_with("http://");
+    let mut rest = s;
+    if *url_before {
+        // the spaces after the URL are left out like in the middle of `s`, since the line break is already there
+        let trimmed = rest.trim_start_matches([' ', '\t']);
+        rest = trimmed.strip_prefix('\n').unwrap_or(trimmed);
+        if rest.is_empty() {
+            return Cow::Owned(String::new());
+        }
+        *url_before = false;
+    }
+    if !rest.split_whitespace().any(is_url) {
+        return if rest.len() == s.len() { Cow::Borrowed(s) } else { Cow::Owned(rest.to_owned()) };
+    }
+
+    let mut out = String::with_capacity(rest.len());
+    let mut after_url = false;
+    while !rest.is_empty() {
+        let word_start = rest.find(|c: char| !c.is_whitespace()).unwrap_or(rest.len());
+        let space = &rest[..word_start];
+        rest = &rest[word_start..];
+        let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let word = &rest[..word_end];
+        rest = &rest[word_end..];
+
+        let url = is_url(word);
+        if (after_url || url) && !space.contains('\n') && !(out.is_empty() && at_line_start) {
+            // spaces and tabs around a URL become a line break, other whitespace is kept
+            out.push_str(space.trim_matches(|c| c == ' ' || c == '\t'));
+            out.push('\n');
+        } else {
+            out.push_str(space);
+        }
+        out.push_str(word);
+        after_url = url;
+        at_line_start = false;
+    }
+    if after_url {
+        out.push('\n');
+    }
+    *url_before = after_url;
+    Cow::Owned(out)
+}
+
+/// Wraps lines longer than `width` characters by replacing the spaces before the words that don't fit with newlines
+///
+/// `column` is how far into a line `s` starts. Returns `None` if nothing needed wrapping.
+fn wrap_lines(s: &str, width: usize, mut column: usize) -> Option<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut wrapped = false;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let word_start = rest.find(|c| c != ' ').unwrap_or(rest.len());
+        let spaces = &rest[..word_start];
+        rest = &rest[word_start..];
+        let word_end = rest.find([' ', '\n']).unwrap_or(rest.len());
+        let word = &rest[..word_end];
+        rest = &rest[word_end..];
+
+        let word_len = word.chars().count();
+        if column > 0 && !word.is_empty() && column + spaces.len() + word_len > width {
+            out.push('\n');
+            column = 0;
+            wrapped = true;
+        } else {
+            out.push_str(spaces);
+            column += spaces.len();
+        }
+        out.push_str(word);
+        column += word_len;
+
+        if rest.starts_with('\n') {
+            out.push('\n');
+            column = 0;
+            rest = &rest[1..];
+        }
+    }
+    if wrapped {
+        Some(out)
+    } else {
+        None
+    }
+}
+
+/// The byte index of the last place before `max` where `f` says `s` can be split
+///
+/// Places that would leave a closing bracket or quote at the start of the next message are avoided if there are others.
+/// Places in the middle of a custom emoji or one of `atomic` are never used, nor of any Discord token if `discord_tokens` is set.
+fn nice_split_index<F: FnMut(char) -> bool>(s: &str, max: usize, mut f: F, atomic: &[String], discord_tokens: bool) -> Option<usize> {
+    let mut orphaning_index = None;
+    for (index, c) in s[..max].char_indices().rev() {
+        if f(c) {
+            let index = index + c.len_utf8();
+            if atomic_start(s, index, atomic, discord_tokens).is_some() {
+                continue;
+            }
+            if !s[index..].starts_with([')', ']', '}', '"', '\'', '»', '”', '’']) {
+                return Some(index);
+            }
+            orphaning_index.get_or_insert(index);
+        }
+    }
+    orphaning_index
+}
+
+/// The byte index at which a custom emoji or one of `atomic` starts, if `index` is in the middle of one
+///
+/// If `discord_tokens` is set, this goes for any other Discord token too, see `discord_token_start`.
+fn atomic_start(s: &str, index: usize, atomic: &[String], discord_tokens: bool) -> Option<usize> {
+    if let Some(start) = custom_emoji_start(s, index) {
+        return Some(start);
+    }
+    if discord_tokens {
+        if let Some(start) = discord_token_start(s, index) {
+            return Some(start);
+        }
+    }
+    atomic.iter().filter(|pattern| pattern.len() > 1).find_map(|pattern| {
+        let mut lo = index.saturating_sub(pattern.len() - 1);
+        while !s.is_char_boundary(lo) {
+            lo -= 1;
+        }
+        let mut hi = (index + pattern.len() - 1).min(s.len());
+        while !s.is_char_boundary(hi) {
+            hi += 1;
+        }
+        s[lo..hi].match_indices(&**pattern).map(|(i, _)| lo + i).find(|&start| start < index && index < start + pattern.len())
+    })
+}
+
+/// The byte index at which a custom emoji like `<:name:1234>` or `<a:name:1234>` starts, if `index` is in the middle of one
+fn custom_emoji_start(s: &str, index: usize) -> Option<usize> {
+    let start = s[..index].rfind('<')?;
+    let token = &s[start..];
+    let rest = token.strip_prefix("<:").or_else(|| token.strip_prefix("<a:"))?;
+    let name_len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))?;
+    let id = rest[name_len..].strip_prefix(':').filter(|_| name_len > 0)?;
+    let id_len = id.find(|c: char| !c.is_ascii_digit())?;
+    if id_len == 0 || !id[id_len..].starts_with('>') {
+        return None;
+    }
+    let end = start + (token.len() - id.len()) + id_len + 1;
+    if index < end {
+        Some(start)
+    } else {
+        None
+    }
+}
+
+/// The byte index at which a Discord token starts, if `index` is in the middle of one
+///
+/// The tokens are mentions of users, roles and channels like `<@1234>`, `<@!1234>`, `<@&1234>` and `<#1234>`,
+/// timestamps like `<t:1700000000>` or `<t:1700000000:R>` and slash commands like `</name:1234>`.
+/// Custom emojis are found by `custom_emoji_start`.
+fn discord_token_start(s: &str, index: usize) -> Option<usize> {
+    let start = s[..index].rfind('<')?;
+    let len = s[start..].find('>')?;
+    if index > start + len {
+        return None;
+    }
+    let inner = &s[start + 1..start + len];
+    let is_id = |id: &str| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit());
+    let is_token = if let Some(time) = inner.strip_prefix("t:") {
+        match time.split_once(':') {
+            Some((time, style)) => is_id(time) && style.len() == 1 && style.bytes().all(|b| b.is_ascii_alphabetic()),
+            None => is_id(time),
+        }
+    } else if let Some(id) = ["@!", "@&", "@", "#"].iter().find_map(|prefix| inner.strip_prefix(prefix)) {
+        is_id(id)
+    } else if let Some(command) = inner.strip_prefix('/') {
+        command.rsplit_once(':').is_some_and(|(name, id)| !name.is_empty() && is_id(id))
+    } else {
+        false
+    };
+    Some(start).filter(|_| is_token)
+}
+
+/// The byte index just after the last `.`, `?` or `!` before `max` in `s` that's followed by whitespace
+fn sentence_break(s: &str, max: usize) -> Option<usize> {
+    s[..max]
+        .char_indices()
+        .rev()
+        .find(|&(i, c)| matches!(c, '.' | '?' | '!') && s[i + 1..].starts_with(char::is_whitespace))
+        .map(|(i, _)| i + 1)
+}
+
+/// Closes spoilers, `||like this||`, that are open at the end of a message and opens them again in the next one
+///
+/// Pairs of `||` are counted from the start, so `||a|| ||b||` is two spoilers and `||||` an empty one.
+/// Bars in code blocks don't count. A spoiler opened at the very end of a message is moved to the next one instead.
+fn balance_spoilers(messages: &mut [String]) {
+    let mut open = false;
+    let mut in_code_block = false;
+    for i in 0..messages.len() {
+        let msg = messages[i].as_bytes();
+        let mut last_open = None;
+        let mut j = 0;
+        while j < msg.len() {
+            if msg[j..].starts_with(b"```") {
+                in_code_block = !in_code_block;
+                j += 3;
+            } else if !in_code_block && msg[j..].starts_with(b"||") {
+                open = !open;
+                if open {
+                    last_open = Some(j);
+                }
+                j += 2;
+            } else {
+                j += 1;
+            }
+        }
+        if open && i + 1 < messages.len() {
+            let len = msg.len();
+            if last_open == Some(len - 2) {
+                messages[i].truncate(len - 2);
+            } else {
+                messages[i].push_str("||");
+            }
+            messages[i + 1].insert_str(0, "||");
+            // the next message opens it again
+            open = false;
+        }
+    }
+}
+
+#[inline]
+/// The byte index just after the last blank line in `s`
+fn blank_line_break(s: &str) -> Option<usize> {
+    s.rfind("\n\n").map(|i| i + 2)
+}
+
+#[inline]
+/// The characters `end_section` considers nice to split after
+fn is_nice_split(c: char) -> bool {
+    matches!(c, ';' | ',' | '.' | '?' | '!' | ')' | ':' | '-')
+}
+
+#[inline]
+/// Whether `line` is a Discord heading, which starts with `# `, `## ` or `### `
+fn is_heading(line: &str) -> bool {
+    ["# ", "## ", "### "].iter().any(|prefix| line.starts_with(prefix))
+}
+
+/// Finds the language tag of every fenced code block in `text`
+fn code_block_languages(text: &str) -> Vec<&str> {
+    let mut langs = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find("```") {
+        let after_open = &rest[open + 3..];
+        let lang = match after_open.find('\n') {
+            Some(end) if !after_open[..end].contains(char::is_whitespace) && !after_open[..end].contains('`') => &after_open[..end],
+            _ => "",
+        };
+        langs.push(lang);
+
+        match after_open.find("```") {
+            Some(close) => rest = &after_open[close + 3..],
+            None => break,
+        }
+    }
+    langs
+}
+
+impl IntoIterator for MsgBunch {
+    type IntoIter = VecIntoIter<String>;
+    type Item = String;
+
+    #[inline(always)]
+    fn into_iter(self) -> Self::IntoIter {
+        self.messages.into_iter()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What a `MsgBunchBuilder` should do when the content doesn't fit in one message
+pub enum OverflowPolicy {
+    /// Split the content into as many messages as needed
+    Split,
+    /// Keep at most `max_messages` messages and cut off the rest of the content
+    ///
+    /// The cut is marked with the truncation marker, see `MsgBunchBuilder::truncation_marker`.
+    /// At least one message is always kept.
+    Truncate {
+        /// The maximum amount of messages to keep
+        max_messages: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What happened when adding a string with `MsgBunchBuilder::try_add_string`
+pub enum AddOutcome {
+    /// The string fit in the current message, or was put into the current section
+    Fit,
+    /// The string didn't fit so it was continued in a new message at a nice point
+    NewMessage,
+    /// The string had to be split somewhere that isn't nice
+    HardSplit,
+}
+
+impl Default for OverflowPolicy {
+    #[inline(always)]
+    fn default() -> Self {
+        OverflowPolicy::Split
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Options for `MsgBunchBuilder::add_user_list_with`
+pub struct UserListOptions {
+    /// Whether to mention each user like `<@id> (name)` instead of only writing their name
+    pub mention: bool,
+    /// Whether to sort the users by name
+    pub sort: bool,
+}
+
+impl Default for UserListOptions {
+    #[inline(always)]
+    fn default() -> Self {
+        UserListOptions {
+            mention: true,
+            sort: false,
+        }
+    }
+}
+
+#[cfg(feature = "unicode-segmentation")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What to do with a grapheme cluster that is too long to fit in a message by itself,
+/// see `MsgBunchBuilder::keep_graphemes`
+pub enum OversizedGrapheme {
+    /// Split the grapheme cluster between its characters, garbling it
+    Split,
+    /// Split it like `Split` but make `try_build` return `BuildError::GraphemeTooLong`
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What a `MsgBunchBuilder` should do with a word that is too long to fit in a message by itself, like a huge blob of base64
+pub enum OversizedToken {
+    /// Split the word like anything else
+    Split,
+    /// Replace the word with `placeholder` and keep it aside in `MsgBunch::omitted`, e.g. to attach it as a file
+    ///
+    /// Any `{}` in the placeholder is replaced with the amount of characters omitted,
+    /// like `"[content too long, {} chars omitted]"`.
+    Replace {
+        /// The text to put instead of the word
+        placeholder: String,
+    },
+}
+
+impl Default for OversizedToken {
+    #[inline(always)]
+    fn default() -> Self {
+        OversizedToken::Split
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+/// The ways building an `MsgBunch` can fail
+pub enum BuildError {
+    /// A message ended up longer than the limit
+    MessageTooLong {
+        /// The index of the message
+        index: usize,
+        /// The length of the message
+        len: usize,
+        /// The limit it should have been within
+        limit: usize,
+    },
+    /// A section was too long for one message and had no point where it was allowed to be split
+    ///
+    /// The section is hard split instead.
+    NoSplitPoint {
+        /// The start of the section
+        section_preview: String,
+    },
+    /// A grapheme cluster was too long to fit in a message by itself
+    GraphemeTooLong {
+        /// The length of the grapheme cluster
+        len: usize,
+        /// The length available in the message
+        limit: usize,
+    },
+    /// A section was split over several messages even though `MsgBunchBuilder::strict_sections` was set
+    SectionSplit {
+        /// The index of the section, counting every section ended
+        section_index: usize,
+        /// The start of the section
+        section_preview: String,
+    },
+    /// The content didn't fit in one message, see `MsgBunchBuilder::build_single`
+    NotSingleMessage {
+        /// How much longer the content is than the limit
+        over: usize,
+        /// The limit of the message
+        limit: usize,
+    },
+    /// A width to pad lines to was wider than the limit
+    WidthTooLarge {
+        /// The width asked for
+        width: usize,
+        /// The limit of the message
+        limit: usize,
+    },
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::MessageTooLong { index, len, limit } => write!(f, "message {} is {} long which is over the limit of {}", index, len, limit),
+            BuildError::NoSplitPoint { section_preview } => write!(f, "found nowhere to split the section starting with {:?}", section_preview),
+            BuildError::GraphemeTooLong { len, limit } => write!(f, "grapheme cluster is {} long which doesn't fit in the {} available", len, limit),
+            BuildError::SectionSplit { section_index, section_preview } => write!(f, "section {} starting with {:?} was split over several messages", section_index, section_preview),
+            BuildError::NotSingleMessage { over, limit } => write!(f, "content is {} over the limit of {} for one message", over, limit),
+            BuildError::WidthTooLarge { width, limit } => write!(f, "width {} is over the limit of {}", width, limit),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+/// What lines repeated with `MsgBunchBuilder::overlap_lines` start with
+const OVERLAP_MARKER: &str = "> ";
+
+/// How many characters of a section to include in errors
+const PREVIEW_CHARS: usize = 50;
+
+/// A function making a footer from how many messages come after, see `MsgBunchBuilder::remaining_footer`
+struct RemainingFooter(Box<dyn Fn(usize) -> String>);
+
+impl fmt::Debug for RemainingFooter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("RemainingFooter")
+    }
+}
+
+type TapFn = dyn FnMut(usize, &str);
+
+/// A function given every built message, see `MsgBunchBuilder::tap`
+struct Tap(Box<TapFn>);
+
+impl fmt::Debug for Tap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Tap")
+    }
+}
+
+#[derive(Debug)]
+/// Structure for making an `MsgBunch` that allows control over where the messsage can be split
+pub struct MsgBunchBuilder {
+    /// the inner `MsgBunch` being worked on
+    /// will not contain the current split section
+    /// use `build` to make sure you get the full thing
+    pub inner: MsgBunch,
+    msg_limit: usize,
+    first_message_limit: Option<usize>,
+    length_mode: LengthMode,
+    chars_num: usize, 
+    no_split_section: Option<(String, usize)>,
+    sticky_prefix: String,
+    continued_header: String,
+    remaining_footer: Option<RemainingFooter>,
+    trailer_message: Option<String>,
+    tap: Option<Tap>,
+    /// the length reserved for the remaining footer
+    remaining_footer_len: usize,
+    split_marker: Option<String>,
+    overflow_policy: OverflowPolicy,
+    truncation_marker: String,
+    oversized_tokens: OversizedToken,
+    sanitize_mentions: bool,
+    break_before_headings: bool,
+    code_blocks_own_message: bool,
+    in_code_block: bool,
+    normalize_whitespace: bool,
+    plain_text: bool,
+    urls_own_line: bool,
+    /// whether what was added last ends with a URL that `urls_own_line` put a line break after
+    url_before: bool,
+    soft_wrap: usize,
+    atomic_substrings: Vec<String>,
+    preserve_discord_tokens: bool,
+    max_lines_per_message: usize,
+    max_code_lines: usize,
+    prefer_blank_line_breaks: bool,
+    preserve_spoilers: bool,
+    first_message_whole_sentences: bool,
+    record_sections: bool,
+    /// the name of the section in progress
+    section_name: Option<String>,
+    strict_sections: bool,
+    /// how many sections have been ended so far
+    sections_ended: usize,
+    section_join: String,
+    /// what continues a section in a new message when it's split in the middle of a line, like a list item's indentation
+    section_indent: String,
+    trailing_newline: bool,
+    overlap_lines: usize,
+    /// how many lines at the start of the current message are repeated from the previous one
+    overlap_in_cur_msg: usize,
+    /// the index of every message that starts with something the builder put there itself, like repeated lines
+    /// or `section_indent`, and how many bytes long that is
+    inserted: Vec<(usize, usize)>,
+    /// the indices of the messages that were started on purpose rather than because content didn't fit
+    forced_breaks: Vec<usize>,
+    /// the first and last message index of every line added with `add_lines`
+    line_spans: Vec<(usize, usize)>,
+    #[cfg(feature = "unicode-segmentation")]
+    keep_graphemes: Option<OversizedGrapheme>,
+    error: Option<BuildError>,
+    /// how many times content had to be split somewhere that isn't nice
+    hard_splits: usize,
+    #[cfg(feature = "stats")]
+    stats: StatsTracker,
+}
+
+#[cfg(feature = "stats")]
+#[derive(Debug)]
+struct StatsTracker {
+    /// when content was first added
+    started: Option<Instant>,
+    input_chars: usize,
+    sections: usize,
+}
+
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Statistics about the building of a `MsgBunch`, see `MsgBunchBuilder::build_with_stats`
+pub struct BuildStats {
+    /// The amount of characters of content given to the builder
+    ///
+    /// What the builder puts in itself, like the newline after every line, code block fences or Markdown syntax,
+    /// isn't counted, and content is counted as it was given, before e.g. `sanitize_mentions` changed it.
+    pub input_chars: usize,
+    /// The amount of messages in the built `MsgBunch`
+    pub output_messages: usize,
+    /// How many times a message had to be split without being able to choose where
+    pub hard_splits: usize,
+    /// How many sections were ended
+    pub sections: usize,
+    /// Time from when content was first added to the builder until it was built, zero if none was
+    pub elapsed: Duration,
+}
+
+impl Default for MsgBunchBuilder {
+    #[inline(always)]
+    fn default() -> Self {
+        MsgBunchBuilder::new()
+    }
+}
+
+impl MsgBunchBuilder {
+    #[inline]
+    /// Begin making an `MsgBunch`
+    pub fn new() -> Self {
+        MsgBunchBuilder::for_platform(Platform::Discord)
+    }
+
+    /// Begin making an `MsgBunch` where messages are at most `limit` characters
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is 0
+    pub fn with_limit(limit: usize) -> Self {
+        assert!(limit > 0, "message limit must be positive");
+        MsgBunchBuilder::with_limit_and_mode(limit, LengthMode::Chars)
+    }
+
+    /// Begin making an `MsgBunch` with the limit and length mode of the given platform
+    #[inline]
+    pub fn for_platform(platform: Platform) -> Self {
+        MsgBunchBuilder::with_limit_and_mode(platform.limit(), platform.length_mode())
+    }
+
+    fn with_limit_and_mode(msg_limit: usize, length_mode: LengthMode) -> Self {
+        MsgBunchBuilder {
+            inner: MsgBunch::new(msg_limit, length_mode),
+            msg_limit,
+            first_message_limit: None,
+            length_mode,
+            chars_num: 0,
+            no_split_section: None,
+            sticky_prefix: String::new(),
+            continued_header: String::new(),
+            remaining_footer: None,
+            trailer_message: None,
+            tap: None,
+            remaining_footer_len: 0,
+            split_marker: None,
+            overflow_policy: OverflowPolicy::Split,
+            truncation_marker: "…".to_owned(),
+            oversized_tokens: OversizedToken::Split,
+            sanitize_mentions: true,
+            break_before_headings: false,
+            code_blocks_own_message: false,
+            in_code_block: false,
+            normalize_whitespace: false,
+            plain_text: false,
+            urls_own_line: false,
+            url_before: false,
+            soft_wrap: 0,
+            atomic_substrings: Vec::new(),
+            preserve_discord_tokens: false,
+            max_lines_per_message: 0,
+            max_code_lines: 0,
+            prefer_blank_line_breaks: false,
+            preserve_spoilers: false,
+            first_message_whole_sentences: false,
+            record_sections: false,
+            section_name: None,
+            strict_sections: false,
+            sections_ended: 0,
+            section_join: String::new(),
+            section_indent: String::new(),
+            trailing_newline: true,
+            overlap_lines: 0,
+            overlap_in_cur_msg: 0,
+            inserted: Vec::new(),
+            forced_breaks: Vec::new(),
+            line_spans: Vec::new(),
+            #[cfg(feature = "unicode-segmentation")]
+            keep_graphemes: None,
+            error: None,
+            hard_splits: 0,
+            #[cfg(feature = "stats")]
+            stats: StatsTracker {
+                started: None,
+                input_chars: 0,
+                sections: 0,
+            },
+        }
+    }
+
+    /// Sets how the length of messages is measured, instead of the way the platform does
+    ///
+    /// This should be set before anything is added.
+    pub fn length_mode(&mut self, length_mode: LengthMode) -> &mut Self {
+        self.length_mode = length_mode;
+        self.inner.length_mode = length_mode;
+        self.chars_num = length_mode.len(self.inner.messages.last().unwrap());
+        self
+    }
+
+    /// Sets a prefix that is put at the start of every message, e.g. a speaker label like `"Narrator: "`
+    ///
+    /// The length of the prefix is reserved from the limit of every message.
+    pub fn sticky_prefix(&mut self, s: &str) -> &mut Self {
+        self.sticky_prefix = s.to_owned();
+        self
+    }
+
+    /// Sets a marker, like `"<!--split-->"`, that forces a message break wherever it appears in added strings
+    ///
+    /// The marker itself is removed from the output. It must appear whole within a single call to `add_string`.
+    pub fn split_marker(&mut self, marker: &str) -> &mut Self {
+        self.split_marker = if marker.is_empty() { None } else { Some(marker.to_owned()) };
+        self
+    }
+
+    /// Sets what to do when the content doesn't fit in one message, by default it's split
+    pub fn overflow_policy(&mut self, policy: OverflowPolicy) -> &mut Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Sets the marker put at the end of the last message when `OverflowPolicy::Truncate` cuts off content
+    ///
+    /// The marker counts against the limit of the message, and is cut short if it's longer. By default it's `"…"`.
+    pub fn truncation_marker(&mut self, marker: &str) -> &mut Self {
+        self.truncation_marker = marker.to_owned();
+        self
+    }
+
+    /// Sets what to do with words that are too long to fit in a message by themselves, by default they're split
+    ///
+    /// Words are separated by whitespace and can't span several calls to `add_string`.
+    pub fn oversized_tokens(&mut self, policy: OversizedToken) -> &mut Self {
+        self.oversized_tokens = policy;
+        self
+    }
+
+    /// Sets whether mentions in added strings should be defused with `sanitize_mentions`, which they are by default
+    ///
+    /// This keeps user provided text that is echoed back from pinging anyone, so only turn it off for trusted text.
+    /// A mention split over several calls to `add_string` isn't caught.
+    pub fn sanitize_mentions(&mut self, sanitize: bool) -> &mut Self {
+        self.sanitize_mentions = sanitize;
+        self
+    }
+
+    /// Sets whether headings, lines starting with `# `, `## ` or `### `, added with `add_lines` should begin a new message
+    ///
+    /// This keeps Markdown headings at the top of messages.
+    pub fn break_before_headings(&mut self, break_before: bool) -> &mut Self {
+        self.break_before_headings = break_before;
+        self
+    }
+
+    #[cfg(feature = "unicode-segmentation")]
+    /// Makes hard splits only happen between grapheme clusters, so e.g. emoji made of several characters aren't broken
+    ///
+    /// `policy` decides what happens when a grapheme cluster is too long to fit in a message by itself.
+    pub fn keep_graphemes(&mut self, policy: OversizedGrapheme) -> &mut Self {
+        self.keep_graphemes = Some(policy);
+        self
+    }
+
+    /// Sets whether fenced code blocks should always be in messages of their own, never sharing a message with other text
+    ///
+    /// The fences have to be whole within a single call to `add_string`.
+    pub fn code_blocks_own_message(&mut self, own_message: bool) -> &mut Self {
+        self.code_blocks_own_message = own_message;
+        self
+    }
+
+    /// Sets whether to escape all Markdown in what's added, so it shows up exactly as written
+    ///
+    /// See `escape_markdown` for what's escaped. Code blocks and headings are escaped too,
+    /// so code blocks aren't given their own message and there are no headings to break before.
+    pub fn plain_text(&mut self, plain: bool) -> &mut Self {
+        self.plain_text = plain;
+        self
+    }
+
+    /// Sets whether to collapse runs of spaces and tabs into one space and trim lines
+    ///
+    /// This is done before the length is counted. Code blocks and inline code are left alone.
+    pub fn normalize_whitespace(&mut self, normalize: bool) -> &mut Self {
+        self.normalize_whitespace = normalize;
+        self
+    }
+
+    /// Sets whether to put URLs on their own line, so Discord makes their embeds in a predictable order
+    ///
+    /// URLs are words starting with `http://` or `https://`, and the spaces around them are replaced by newlines.
+    /// URLs in code blocks are left alone.
+    pub fn urls_own_line(&mut self, own_line: bool) -> &mut Self {
+        self.urls_own_line = own_line;
+        self
+    }
+
+    /// Sets a width in characters to wrap lines longer than at word boundaries, `0` meaning no wrapping
+    ///
+    /// Spaces where a line is wrapped are replaced by a newline, which counts against the limit.
+    /// Words longer than `width` are left on a line of their own and lines in code blocks aren't wrapped.
+    pub fn soft_wrap(&mut self, width: usize) -> &mut Self {
+        self.soft_wrap = width;
+        self
+    }
+
+    /// Sets a header that is put at the start of every message but the first, e.g. `"(continued)\n"`
+    ///
+    /// It goes before the sticky prefix and its length is reserved from the limit of those messages.
+    pub fn continued_header(&mut self, s: &str) -> &mut Self {
+        self.continued_header = s.to_owned();
+        self
+    }
+
+    /// Sets whether to split messages after a blank line when there's one before the limit
+    ///
+    /// This goes before the usual ways of splitting. Content outside of sections is then split
+    /// at whitespace if there's no blank line, and only hard split if there's no whitespace either.
+    pub fn prefer_blank_line_breaks(&mut self, prefer: bool) -> &mut Self {
+        self.prefer_blank_line_breaks = prefer;
+        self
+    }
+
+    /// Sets whether spoilers split over messages should be closed and opened again, so they stay hidden in both
+    ///
+    /// Room for closing and opening a spoiler is reserved from the limit of every message,
+    /// and content isn't hard split between the two bars of a `||`.
+    pub fn preserve_spoilers(&mut self, preserve: bool) -> &mut Self {
+        self.preserve_spoilers = preserve;
+        self
+    }
+
+    /// Sets whether the last message is allowed to end with a newline, which it is by default
+    ///
+    /// If not, one newline at the very end is removed when building, like the one `add_lines` puts after the last line.
+    /// Newlines at the end of other messages are left alone.
+    pub fn trailing_newline(&mut self, allowed: bool) -> &mut Self {
+        self.trailing_newline = allowed;
+        self
+    }
+
+    /// Sets a function making a footer for every message but the last, e.g. `|n| format!("\n… {} more below", n)`
+    ///
+    /// The function is given how many messages come after the one the footer is for.
+    /// How many messages there will be isn't known while adding content, so the length of the longest footer
+    /// for up to 999 messages to come is reserved from the limit of every message.
+    pub fn remaining_footer<F: Fn(usize) -> String + 'static>(&mut self, f: F) -> &mut Self {
+        self.remaining_footer_len = [1, 9, 99, 999].iter().map(|&n| self.length_mode.len(&f(n))).max().unwrap();
+        self.remaining_footer = Some(RemainingFooter(Box::new(f)));
+        self
+    }
+
+    /// Sets a function that's given the index and content of every message when building, e.g. to log them
+    ///
+    /// It's called in order with the messages as they are in the built `MsgBunch`,
+    /// after every prefix, header and footer has been added, and not at all if building fails.
+    pub fn tap<F: FnMut(usize, &str) + 'static>(&mut self, f: F) -> &mut Self {
+        self.tap = Some(Tap(Box::new(f)));
+        self
+    }
+
+    /// Sets how many lines the code blocks of `add_code_block_lines` and `add_table` can have in a message, `0` meaning no maximum
+    ///
+    /// After every `n` lines the code block is closed and continues in a new message,
+    /// starting with a `(continued)` comment in the syntax of its language.
+    pub fn max_code_lines(&mut self, n: usize) -> &mut Self {
+        self.max_code_lines = n;
+        self
+    }
+
+    /// Sets how many lines a message can have at most, `0` meaning no maximum
+    ///
+    /// A message ends when it reaches either this or the limit, whichever comes first.
+    pub fn max_lines_per_message(&mut self, n: usize) -> &mut Self {
+        self.max_lines_per_message = n;
+        self
+    }
+
+    /// Sets a message that's always sent last, after all the content, even if there is no content
+    ///
+    /// It's added as it is when building, without the sticky prefix or any other decoration,
+    /// and `try_build` fails with `BuildError::MessageTooLong` if it's over the limit.
+    pub fn trailer_message(&mut self, s: String) -> &mut Self {
+        self.trailer_message = Some(s);
+        self
+    }
+
+    /// Sets substrings, like product codes or hashes, that are never split
+    ///
+    /// Custom emojis are never split either. One that doesn't fit in a message by itself is hard split anyway.
+    pub fn atomic_substrings(&mut self, patterns: Vec<String>) -> &mut Self {
+        self.atomic_substrings = patterns;
+        self
+    }
+
+    /// Sets whether Discord tokens like `<@1234>` mentions, `<#1234>` channels and `<t:1700000000:R>` timestamps are never split
+    ///
+    /// Custom emojis are never split anyway. Like atomic substrings, a token that doesn't fit in a message by itself is hard split.
+    pub fn preserve_discord_tokens(&mut self, preserve: bool) -> &mut Self {
+        self.preserve_discord_tokens = preserve;
+        self
+    }
+
+    /// Sets whether the first message should end after a whole sentence when content has to be split out of it, e.g. for a teaser
+    ///
+    /// The first message is then split after the last `.`, `?` or `!` followed by whitespace that fits, if there is one,
+    /// even if that leaves it well under the limit. Other messages are split as usual.
+    pub fn first_message_whole_sentences(&mut self, whole: bool) -> &mut Self {
+        self.first_message_whole_sentences = whole;
+        self
+    }
+
+    /// Sets a separator, like a space or newline, that's put between a section and what comes before it in the same message
+    ///
+    /// It isn't put at the start of a message, and it counts against the limit.
+    pub fn section_join(&mut self, s: &str) -> &mut Self {
+        self.section_join = s.to_owned();
+        self
+    }
+
+    /// Sets how many of the last lines of a message to repeat at the start of the next one when content doesn't fit
+    ///
+    /// The repeated lines are quoted with `> ` and count against the limit.
+    /// They're left out if they would take up half of the next message or more, or if the section that follows wouldn't fit with them,
+    /// and they aren't repeated at message breaks that are asked for, like with `break_message`.
+    /// Nothing is repeated when content is split in the middle of a line.
+    pub fn overlap_lines(&mut self, n: usize) -> &mut Self {
+        self.overlap_lines = n;
+        self
+    }
+
+    /// Sets whether `try_build` should fail with `BuildError::SectionSplit` if any section doesn't fit in one message
+    pub fn strict_sections(&mut self, strict: bool) -> &mut Self {
+        self.strict_sections = strict;
+        self
+    }
+
+    /// Sets whether to record where each section ends up, see `MsgBunch::section_placements`
+    pub fn record_sections(&mut self, record: bool) -> &mut Self {
+        self.record_sections = record;
+        self
+    }
+
+    /// Forces whatever is added next to begin in a new message
+    ///
+    /// If a section is in progress, it is ended with `end_section` and a new section is begun in the new message.
+    pub fn break_message(&mut self) -> &mut Self {
+        if self.is_in_section() {
+            self.end_section();
+            self.new_message();
+            self.begin_section();
+        } else {
+            self.new_message();
+        }
+        self
+    }
+
+    /// Sets a different limit for the first message only, e.g. because it's sent alongside an embed
+    ///
+    /// All other messages keep the normal limit.
+    pub fn first_message_limit(&mut self, limit: usize) -> &mut Self {
+        self.first_message_limit = Some(limit);
+        self
+    }
+
+    /// The limit of the message at `index`
+    fn full_limit_at(&self, index: usize) -> usize {
+        match self.first_message_limit {
+            Some(limit) if index == 0 => limit,
+            _ => self.msg_limit,
+        }
+    }
+
+    /// The length available for content in the message at `index`
+    fn limit_at(&self, index: usize) -> usize {
+        let mut reserved = self.length_mode.len(&self.sticky_prefix) + self.remaining_footer_len;
+        if self.preserve_spoilers {
+            reserved += self.length_mode.len("||||");
+        }
+        if index > 0 {
+            reserved += self.length_mode.len(&self.continued_header);
+        }
+        self.full_limit_at(index).saturating_sub(reserved).max(1)
+    }
+
+    #[inline]
+    /// The length available for content in the current message
+    fn limit(&self) -> usize {
+        self.limit_at(self.inner.messages.len() - 1)
+    }
+
+    /// The byte index at which `s` should be split for the first part to be at most `max` long
+    /// when there is no nicer place to split
+    ///
+    /// A backslash isn't split from the character it escapes and custom emojis and atomic substrings aren't split at all.
+    /// Records an error if the split can't be made in a way that was asked for.
+    fn hard_split_index(&mut self, s: &str, max: usize) -> usize {
+        let mut index = self.length_mode.split_index(s, max);
+        // A Markdown escape and the character it escapes are kept together
+        if index > 1 && index < s.len() && s[..index].bytes().rev().take_while(|&b| b == b'\\').count() % 2 == 1 {
+            index -= 1;
+        }
+        // The two bars of a spoiler too, if spoilers are preserved
+        if self.preserve_spoilers && index > 1 && s[index..].starts_with('|') && s[..index].bytes().rev().take_while(|&b| b == b'|').count() % 2 == 1 {
+            index -= 1;
+        }
+        // So is a custom emoji or atomic substring, unless it doesn't fit in a message by itself
+        if let Some(start) = atomic_start(s, index, &self.atomic_substrings, self.preserve_discord_tokens).filter(|&start| start > 0) {
+            index = start;
+        }
+
+        #[cfg(feature = "unicode-segmentation")]
+        {
+            if let Some(policy) = self.keep_graphemes {
+                use unicode_segmentation::UnicodeSegmentation;
+
+                match s.grapheme_indices(true).map(|(i, _)| i).take_while(|&i| i <= index).filter(|&i| i > 0).last() {
+                    Some(boundary) => return boundary,
+                    None if index < s.len() => {
+                        if let OversizedGrapheme::Error = policy {
+                            let grapheme = s.graphemes(true).next().unwrap();
+                            let len = self.length_mode.len(grapheme);
+                            self.error(BuildError::GraphemeTooLong { len, limit: max });
+                        }
+                    }
+                    None => (),
+                }
+            }
+        }
+
+        index
+    }
+
+    /// Splits the current message so it's at most `max` long and returns what was split off
+    ///
+    /// It's hard split unless `prefer_blank_line_breaks` is set and there's a blank line or whitespace to split at.
+    fn split_off_current(&mut self, max: usize) -> String {
+        let mut cur_msg = take(self.inner.messages.last_mut().unwrap());
+        let hard_index = self.hard_split_index(&cur_msg, max);
+        let mut index = None;
+        if self.first_message_whole_sentences && self.inner.messages.len() == 1 {
+            index = sentence_break(&cur_msg, hard_index);
+        }
+        if index.is_none() && self.prefer_blank_line_breaks {
+            // the lines repeated from the previous message shouldn't be split off again
+            let start = cur_msg.split_inclusive('\n').take(self.overlap_in_cur_msg).map(str::len).sum();
+            if start < hard_index {
+                let searched = &cur_msg[start..hard_index];
+                index = blank_line_break(searched)
+                    .or_else(|| searched.rfind(char::is_whitespace).map(|i| i + searched[i..].chars().next().unwrap().len_utf8()))
+                    .map(|i| start + i)
+                    .filter(|&i| atomic_start(&cur_msg, i, &self.atomic_substrings, self.preserve_discord_tokens).is_none());
+            }
+        }
+        let index = index.unwrap_or_else(|| {
+            self.hard_splits += 1;
+            self.inner.hard_split_before.push(self.inner.messages.len());
+            hard_index
+        });
+        let new_cur_msg = cur_msg.split_off(index);
+        *self.inner.messages.last_mut().unwrap() = cur_msg;
+        new_cur_msg
+    }
+
+    /// Records an error to be returned by `try_build`, only the first error is kept
+    fn error(&mut self, error: BuildError) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    /// Starts a new message unless the current one is still empty
+    fn new_message(&mut self) {
+        if self.chars_num > 0 {
+            self.inner.messages.push(String::with_capacity(self.msg_limit));
+            self.chars_num = 0;
+            self.overlap_in_cur_msg = 0;
+            self.forced_breaks.push(self.inner.messages.len() - 1);
+        }
+    }
+
+    /// Starts a new message, because the current one is full, unless the current one is still empty
+    ///
+    /// The new message begins with the overlap from the current one, see `overlap_lines`,
+    /// unless `next_size` of content that has to follow wouldn't fit with it.
+    fn overflow_message(&mut self, next_size: usize) {
+        if self.chars_num == 0 {
+            return;
+        }
+        let mut overlap = String::new();
+        let mut overlap_lines = 0;
+        let cur_msg = self.inner.messages.last().unwrap();
+        // Only whole lines are repeated, so there's nothing to repeat when a line is split
+        if self.overlap_lines > 0 && cur_msg.ends_with('\n') {
+            let lines: Vec<_> = cur_msg.split_inclusive('\n').skip(self.overlap_in_cur_msg).collect();
+            for line in &lines[lines.len().saturating_sub(self.overlap_lines)..] {
+                overlap.push_str(OVERLAP_MARKER);
+                overlap.push_str(line.strip_suffix('\n').unwrap_or(line));
+                overlap.push('\n');
+                overlap_lines += 1;
+            }
+            // Without room left for new content, repeating lines doesn't help anyone
+            let overlap_size = self.length_mode.len(&overlap);
+            let next_limit = self.limit_at(self.inner.messages.len());
+            if 2 * overlap_size >= next_limit || overlap_size + next_size > next_limit {
+                overlap.clear();
+                overlap_lines = 0;
+            }
+        }
+        self.chars_num = self.length_mode.len(&overlap);
+        self.overlap_in_cur_msg = overlap_lines;
+        if !overlap.is_empty() {
+            self.inserted.push((self.inner.messages.len(), overlap.len()));
+        }
+        self.inner.messages.push(overlap);
+    }
+
+    /// Adds a string to the `MsgBunch` splitting if necessary
+    /// This changes the way it splits depending on whether it is currently in a section.
+    ///
+    /// Sections are started with `MsgBunchBuilder::begin_section` and ended with `MsgBunchBuilder::end_section`
+    ///
+    /// # Example
+    ///
+    /// For example you tell the builder to try not to split a welcome message.
+    /// It will then try its best not to split in the middle of that section, putting it into its own message if need be.
+    ///
+    /// ```
+    /// # fn get_name() -> String { "Jens".to_owned() }
+    /// # fn motd() -> &'static str {""}
+    /// use discord_utils::MsgBunchBuilder;
+    /// 
+    /// let mut mmb = MsgBunchBuilder::new();
+    /// mmb
+    ///     .begin_section()
+    ///     .add_string("Hello, ")
+    ///     // Some sort of function that gets the name of the user
+    ///     .add_string(get_name())
+    ///     .add_string("!\n")
+    ///     .end_section()
+    ///     // Perhaps the message ends with an motd, that contains a lot of lines.
+    ///     .add_lines(motd());
+    /// 
+    /// let msg_bunch = mmb.build();
+    /// ```
+    pub fn add_string<S: AsRef<str>>(&mut self, s: S) -> &mut Self {
+        self.count_input(Some(s.as_ref()));
+        self.add_text(s.as_ref());
+        self
+    }
+
+    /// Same as `add_string` but for text the builder puts in itself, which isn't counted as input in `BuildStats`
+    fn add_text(&mut self, s: &str) {
+        let mut string_to_add = Cow::Borrowed(s);
+        if self.plain_text {
+            let at_line_start = self.last_char().map_or(true, |c| c == '\n');
+            string_to_add = Cow::Owned(escape_markdown_from(&string_to_add, at_line_start));
+        }
+        if self.sanitize_mentions {
+            string_to_add = Cow::Owned(sanitize_mentions(&string_to_add));
+        }
+        if let Some(replaced) = self.replace_oversized_tokens(&string_to_add) {
+            string_to_add = Cow::Owned(replaced);
+        }
+
+        let mut rest = &*string_to_add;
+        if let Some(marker) = self.split_marker.clone() {
+            while let Some(index) = rest.find(&*marker) {
+                self.add_segments(&rest[..index]);
+                self.break_message();
+                rest = &rest[index + marker.len()..];
+            }
+        }
+        self.add_segments(rest);
+    }
+
+    #[cfg_attr(not(feature = "stats"), allow(unused_variables))]
+    /// Counts `input` as content given to the builder in `BuildStats`, starting the clock if it's the first
+    fn count_input<'a, I: IntoIterator<Item = &'a str>>(&mut self, input: I) {
+        #[cfg(feature = "stats")]
+        {
+            self.stats.started.get_or_insert_with(Instant::now);
+            self.stats.input_chars += input.into_iter().map(|s| s.chars().count()).sum::<usize>();
+        }
+    }
+
+    /// Same as `add_string` but tells what happened while adding it
+    ///
+    /// Inside a section the string is only buffered, so it always fits;
+    /// the section is laid out when it ends.
+    /// Fails with the first error that adding it caused, like `BuildError::GraphemeTooLong`,
+    /// which `try_build` will return as well.
+    pub fn try_add_string<S: AsRef<str>>(&mut self, s: S) -> Result<AddOutcome, BuildError> {
+        let had_error = self.error.is_some();
+        let messages_before = self.inner.messages.len();
+        let hard_splits_before = self.hard_splits;
+
+        self.add_string(s);
+
+        match &self.error {
+            Some(e) if !had_error => Err(e.clone()),
+            _ if self.hard_splits > hard_splits_before => Ok(AddOutcome::HardSplit),
+            _ if self.inner.messages.len() > messages_before => Ok(AddOutcome::NewMessage),
+            _ => Ok(AddOutcome::Fit),
+        }
+    }
+
+    /// Adds a string, keeping track of whether it's in a code block
+    fn add_segments(&mut self, s: &str) {
+        let mut rest = s;
+        while let Some(index) = rest.find("```") {
+            let end = if self.in_code_block {
+                // the closing fence and the rest of its line belong to the code block
+                let after_fence = index + 3;
+                let end = rest[after_fence..].find('\n').map_or(rest.len(), |i| after_fence + i + 1);
+                self.add_str(&rest[..end]);
+                if self.code_blocks_own_message {
+                    self.break_message();
+                }
+                end
+            } else {
+                self.add_prose(&rest[..index]);
+                if self.code_blocks_own_message {
+                    self.break_message();
+                }
+                self.add_str("```");
+                index + 3
+            };
+            self.in_code_block = !self.in_code_block;
+            rest = &rest[end..];
+        }
+        if self.in_code_block {
+            self.add_str(rest);
+        } else {
+            self.add_prose(rest);
+        }
+    }
+
+    /// Adds a string that isn't in a code block
+    fn add_prose(&mut self, s: &str) {
+        let mut prose = Cow::Borrowed(s);
+        let mut url_before = false;
+        if self.normalize_whitespace {
+            let after_space = self.last_char().map_or(true, |c| c == '\n' || c == ' ');
+            prose = Cow::Owned(normalize_whitespace(&prose, after_space));
+        }
+        if self.urls_own_line {
+            let at_line_start = self.last_char().map_or(true, |c| c == '\n');
+            url_before = self.url_before;
+            if let Cow::Owned(s) = urls_on_own_lines(&prose, at_line_start, &mut url_before) {
+                prose = Cow::Owned(s);
+            }
+        }
+        if self.soft_wrap > 0 {
+            if let Some(wrapped) = wrap_lines(&prose, self.soft_wrap, self.current_column()) {
+                prose = Cow::Owned(wrapped);
+            }
+        }
+        self.add_str(&prose);
+        self.url_before = url_before;
+    }
+
+    /// The last character added so far, including the current section
+    fn last_char(&self) -> Option<char> {
+        if let Some((section, _)) = &self.no_split_section {
+            if let Some(c) = section.chars().next_back() {
+                return Some(c);
+            }
+        }
+        self.inner.messages.last().and_then(|msg| msg.chars().next_back())
+    }
+
+    /// How many characters have been added to the current line so far, including the current section
+    fn current_column(&self) -> usize {
+        let mut column = 0;
+        if let Some((section, _)) = &self.no_split_section {
+            match section.rfind('\n') {
+                Some(i) => return section[i + 1..].chars().count(),
+                None => column += section.chars().count(),
+            }
+        }
+        let cur_msg = self.inner.messages.last().map_or("", |msg| &**msg);
+        column + cur_msg[cur_msg.rfind('\n').map_or(0, |i| i + 1)..].chars().count()
+    }
+
+    /// Replaces words that are too long for a message, if `OversizedToken::Replace` is used
+    fn replace_oversized_tokens(&mut self, s: &str) -> Option<String> {
+        let placeholder = match &self.oversized_tokens {
+            OversizedToken::Split => return None,
+            OversizedToken::Replace { placeholder } => placeholder,
+        };
+        let limit = self.limit();
+        let is_oversized = |token: &&str| self.length_mode.len(token) > limit;
+        if !s.split_whitespace().any(|token| is_oversized(&token)) {
+            return None;
+        }
+
+        let mut replaced = String::with_capacity(s.len());
+        let mut omitted = Vec::new();
+        let mut rest = s;
+        while !rest.is_empty() {
+            let token_start = rest.find(|c: char| !c.is_whitespace()).unwrap_or(rest.len());
+            replaced.push_str(&rest[..token_start]);
+            rest = &rest[token_start..];
+
+            let token_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let token = &rest[..token_end];
+            if is_oversized(&token) {
+                replaced.push_str(&placeholder.replace("{}", &group_thousands(token.chars().count())));
+                omitted.push(token.to_owned());
+            } else {
+                replaced.push_str(token);
+            }
+            rest = &rest[token_end..];
+        }
+
+        self.inner.omitted.extend(omitted);
+        Some(replaced)
+    }
+
+    /// Adds a string with no transformations
+    fn add_str(&mut self, string_to_add: &str) {
+        self.url_before = false;
+        let string_to_add_size = self.length_mode.len(string_to_add);
+
+        if let Some((no_split_section, size)) = &mut self.no_split_section {
+            *size += string_to_add_size;
+            no_split_section.push_str(string_to_add);
+        } else {
+            self.inner.messages.last_mut().unwrap().push_str(string_to_add);
+            self.chars_num += string_to_add_size;
+            self.split_overflow();
+        }
+    }
+
+    /// Splits the current message until it is within the limit and `max_lines_per_message`
+    fn split_overflow(&mut self) {
+        loop {
+            let line_index = self.excess_lines_index();
+            if self.chars_num > self.limit() {
+                let cur_msg = self.inner.messages.last().unwrap();
+                if line_index.is_some_and(|i| self.length_mode.len(&cur_msg[..i]) <= self.limit()) {
+                    self.split_off_lines(line_index.unwrap());
+                } else {
+                    let new_cur_msg = self.split_off_current(self.limit());
+                    self.overflow_message(0);
+                    self.chars_num += self.length_mode.len(&new_cur_msg);
+                    self.inner.messages.last_mut().unwrap().push_str(&new_cur_msg);
+                }
+            } else if let Some(index) = line_index {
+                self.split_off_lines(index);
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// The byte index after the last line the current message can have, if there's more after it
+    fn excess_lines_index(&self) -> Option<usize> {
+        if self.max_lines_per_message == 0 {
+            return None;
+        }
+        let cur_msg = self.inner.messages.last().unwrap();
+        cur_msg.match_indices('\n').nth(self.max_lines_per_message - 1).map(|(i, _)| i + 1).filter(|&i| i < cur_msg.len())
+    }
+
+    /// Moves everything after `index` in the current message to a new message
+    fn split_off_lines(&mut self, index: usize) {
+        let new_cur_msg = self.inner.messages.last_mut().unwrap().split_off(index);
+        self.chars_num = self.length_mode.len(&new_cur_msg);
+        self.overlap_in_cur_msg = 0;
+        self.inner.messages.push(new_cur_msg);
+        self.forced_breaks.push(self.inner.messages.len() - 1);
+    }
+
+    /// Begins a section which affects subsequent calls to `add_string`
+    /// 
+    /// Does nothing if a section is already in progress
+    pub fn begin_section(&mut self) -> &mut Self {
+        if self.no_split_section.is_none() {
+            self.no_split_section = Some((String::new(), 0));
+        }
+        self
+    }
+
+    /// Begins a section like `begin_section` and names it,
+    /// so the message it starts in can be found with `MsgBunch::section_message`
+    ///
+    /// Does nothing if a section is already in progress. A later section with the same name replaces the earlier one.
+    pub fn begin_named_section(&mut self, name: &str) -> &mut Self {
+        if self.no_split_section.is_none() {
+            self.section_name = Some(name.to_owned());
+        }
+        self.begin_section()
+    }
+
+    #[inline]
+    /// Whether we are in a section right now.
+    pub fn is_in_section(&self) -> bool {
+        self.no_split_section.is_some()
+    }
+
+    #[inline]
+    /// Ends a section which affects subsequent calls to `add_string`
+    /// 
+    /// If the section is over the limit it will try to split at a nice point, see `end_section_with`
+    /// 
+    /// Does nothing if no section is in progress
+    pub fn end_section(&mut self) -> &mut Self {
+        self.end_section_with(is_nice_split)
+    }
+
+    /// Ends a section which affects subsequent calls to `add_string`
+    /// 
+    /// If the section is over the limit it will try to split at a nice point defined by the provided callback.
+    /// The callback is used to find characters that are appropriate to split at.
+    /// It go travel backwards from the split point, calling the callback until it returns true.
+    /// Places that would leave a closing bracket or quote alone at the start of the next message are skipped if there are others.
+    /// If it finds nowhere to split, the section is hard split and `try_build` will return `BuildError::NoSplitPoint`.
+    /// 
+    /// Does nothing if no section is in progress
+    pub fn end_section_with<F: FnMut(char) -> bool>(&mut self, mut f: F) -> &mut Self {
+        if let Some((mut no_split_section, size)) = self.no_split_section.take() {
+            #[cfg(feature = "stats")]
+            {
+                self.stats.sections += 1;
+            }
+            if self.max_lines_per_message > 0 && self.inner.messages.last().unwrap().matches('\n').count() >= self.max_lines_per_message {
+                self.new_message();
+            }
+            let join_size = if self.chars_num > 0 { self.length_mode.len(&self.section_join) } else { 0 };
+            let section_preview: String = no_split_section.chars().take(PREVIEW_CHARS).collect();
+            let first_index;
+            if self.chars_num + join_size + size > self.limit() {
+                self.overflow_message(size);
+                first_index = self.inner.messages.len() - 1;
+
+                let mut no_split_section_size = size;
+
+                // If the section is longer than the msg limit, we have to split it anyway
+                // using the passed function to check charactes that should allow splits
+                while self.chars_num + no_split_section_size > self.limit() {
+                    let limit = self.limit().saturating_sub(self.chars_num).max(1);
+                    // only looking before the message limit
+                    let hard_index = self.hard_split_index(&no_split_section, limit);
+                    let sentence_index = if self.first_message_whole_sentences && self.inner.messages.len() == 1 {
+                        sentence_break(&no_split_section, hard_index)
+                    } else {
+                        None
+                    };
+                    let blank_line_index = if self.prefer_blank_line_breaks { blank_line_break(&no_split_section[..hard_index]) } else { None };
+                    let nice_index = sentence_index.or(blank_line_index).or_else(|| nice_split_index(&no_split_section, hard_index, &mut f, &self.atomic_substrings, self.preserve_discord_tokens));
+                    let index = match nice_index {
+                        Some(index) => index,
+                        None => {
+                            let section_preview = no_split_section.chars().take(PREVIEW_CHARS).collect();
+                            self.error(BuildError::NoSplitPoint { section_preview });
+                            self.hard_splits += 1;
+                            self.inner.hard_split_before.push(self.inner.messages.len());
+                            hard_index
+                        }
+                    };
+
+                    let new_cur_msg = no_split_section.split_off(index);
+
+                    let first_section = replace(&mut no_split_section, new_cur_msg);
+                    no_split_section_size = self.length_mode.len(&no_split_section);
+
+                    self.chars_num += self.length_mode.len(&first_section);
+                    self.inner.messages.last_mut().unwrap().push_str(&first_section);
+                    self.overflow_message(no_split_section_size);
+                    if !first_section.ends_with('\n') && !self.section_indent.is_empty() {
+                        // the indentation takes the place of any whitespace the continuation starts with
+                        let trimmed = no_split_section.len() - no_split_section.trim_start().len();
+                        no_split_section.replace_range(..trimmed, "");
+                        no_split_section_size = self.length_mode.len(&no_split_section);
+                        self.chars_num += self.length_mode.len(&self.section_indent);
+                        let index = self.inner.messages.len() - 1;
+                        match self.inserted.last_mut() {
+                            Some((i, len)) if *i == index => *len += self.section_indent.len(),
+                            _ => self.inserted.push((index, self.section_indent.len())),
+                        }
+                        self.inner.messages.last_mut().unwrap().push_str(&self.section_indent);
+                    }
+                }
+                self.chars_num += no_split_section_size;
+                self.inner.messages.last_mut().unwrap().push_str(&no_split_section);
+            } else {
+                first_index = self.inner.messages.len() - 1;
+                self.chars_num += join_size + size;
+                let cur_msg = self.inner.messages.last_mut().unwrap();
+                if join_size > 0 {
+                    cur_msg.push_str(&self.section_join);
+                }
+                cur_msg.push_str(&no_split_section)
+            }
+
+            self.split_overflow();
+
+            let split_count = self.inner.messages.len() - 1 - first_index;
+            if self.strict_sections && split_count > 0 {
+                self.error(BuildError::SectionSplit { section_index: self.sections_ended, section_preview });
+            }
+            if let Some(name) = self.section_name.take() {
+                self.inner.named_sections.insert(name, first_index);
+            }
+            if self.record_sections {
+                self.inner.sections.push(SectionPlacement {
+                    section_index: self.sections_ended,
+                    message_index: first_index,
+                    was_split: split_count > 0,
+                    split_count,
+                });
+            }
+            self.sections_ended += 1;
+        }
+        self
+    }
+
+    /// Add lines with each line being a separate section
+    ///
+    /// Every line gets a newline after it, see `trailing_newline` for leaving out the last one.
+    pub fn add_lines<S: AsRef<str>>(&mut self, lines: S) -> &mut Self {
+        self.count_input(Some(lines.as_ref()));
+        for line in lines.as_ref().lines() {
+            if self.break_before_headings && !self.plain_text && is_heading(line) {
+                self.break_message();
+            }
+            let before = self.inner.messages.len() - 1;
+            let before_empty = self.chars_num == 0;
+
+            self.begin_section();
+            self.add_text(line);
+            self.add_text("\n");
+            self.end_section();
+
+            let after = self.inner.messages.len() - 1;
+            let first = if before_empty || after == before { before } else { before + 1 };
+            self.line_spans.push((first, after));
+        }
+
+        self
+    }
+
+    /// Adds lines grouped into records, where a record starts at each line `record_start` returns true for,
+    /// e.g. lines starting with a timestamp
+    ///
+    /// Messages are only split before the start of a record, so every message can be read on its own,
+    /// as long as no record is longer than a message by itself.
+    /// Such a record begins a new message and is hard split, so its continuation won't start with a record.
+    /// Lines before the first record start make up a record of their own.
+    pub fn add_records<S: AsRef<str>, F: Fn(&str) -> bool>(&mut self, text: S, record_start: F) -> &mut Self {
+        let text = text.as_ref();
+        self.count_input(Some(text));
+        let mut starts: Vec<usize> = text.split_inclusive('\n')
+            .scan(0, |offset, line| {
+                let start = *offset;
+                *offset += line.len();
+                Some((start, line))
+            })
+            .filter(|&(start, line)| start > 0 && record_start(line.trim_end_matches('\n')))
+            .map(|(start, _)| start)
+            .collect();
+        starts.insert(0, 0);
+        starts.push(text.len());
+
+        for record in starts.windows(2).map(|w| &text[w[0]..w[1]]).filter(|record| !record.is_empty()) {
+            // the limit of the message the record would be moved to if it doesn't fit in the current one
+            let limit = if self.chars_num == 0 { self.limit() } else { self.limit_at(self.inner.messages.len()) };
+            if self.length_mode.len(record) > limit {
+                self.end_section();
+                self.new_message();
+                self.add_text(record);
+            } else {
+                self.begin_section().add_text(record);
+                self.end_section_with(|_| false);
+            }
+        }
+        self
+    }
+
+    /// Cuts off all messages after the first `max_messages` and puts the truncation marker at the end
+    fn truncate(&mut self, max_messages: usize) {
+        if self.inner.messages.len() <= max_messages {
+            return;
+        }
+        self.inner.messages.truncate(max_messages);
+
+        let limit = self.limit();
+        let mut marker = self.truncation_marker.clone();
+        // a marker too long for the message is cut short and takes up all of it
+        while self.length_mode.len(&marker) > limit && marker.pop().is_some() {}
+        let keep = limit - self.length_mode.len(&marker);
+        if keep == 0 {
+            self.inner.messages.last_mut().unwrap().clear();
+        } else if self.length_mode.len(self.inner.messages.last().unwrap()) > keep {
+            self.split_off_current(keep);
+        }
+        self.inner.messages.last_mut().unwrap().push_str(&marker);
+        self.inner.truncated = true;
+    }
+
+    /// Adds a code block in the language `lang` with the given lines, ending any section in progress
+    ///
+    /// The code block is split between lines if it doesn't fit in a message,
+    /// and the fence is closed at the end of each message and opened again in the next with the same language.
+    /// Only a single line too long for a message by itself is split in the middle.
+    /// See `max_code_lines` for splitting it into pages of a fixed amount of lines instead.
+    pub fn add_code_block_lines<I>(&mut self, lang: &str, lines: I) -> &mut Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        #[cfg(feature = "stats")]
+        let lines: Vec<_> = {
+            let lines: Vec<_> = lines.into_iter().collect();
+            self.count_input(lines.iter().map(AsRef::as_ref));
+            lines
+        };
+        self.add_fenced_lines("", lang, "", lines)
+    }
+
+    /// Adds a table in a code block with the columns padded to line up
+    ///
+    /// The header row is followed by a separator line and the rows, which are split between like `add_code_block_lines`.
+    /// Every message the table continues in starts with the header row and separator line again.
+    /// Widths are approximate like in `add_right_aligned`.
+    pub fn add_table<H: AsRef<str>, C: AsRef<str>>(&mut self, header: &[H], rows: &[Vec<C>]) -> &mut Self {
+        self.count_input(header.iter().map(AsRef::as_ref).chain(rows.iter().flatten().map(AsRef::as_ref)));
+        let columns = rows.iter().map(Vec::len).chain(Some(header.len())).max().unwrap_or(0);
+        let width_of = |cell: &str| cell.chars().map(approx_display_width).sum::<usize>();
+        let mut widths = vec![0; columns];
+        for (i, cell) in header.iter().enumerate() {
+            widths[i] = widths[i].max(width_of(cell.as_ref()));
+        }
+        for row in rows {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(width_of(cell.as_ref()));
+            }
+        }
+
+        let render_row = |cells: &mut dyn Iterator<Item = &str>| {
+            let mut line = String::new();
+            for (i, &width) in widths.iter().enumerate() {
+                let cell = cells.next().unwrap_or("");
+                if i > 0 {
+                    line.push_str(" | ");
+                }
+                line.push_str(cell);
+                line.extend(std::iter::repeat(' ').take(width.saturating_sub(width_of(cell))));
+            }
+            line.truncate(line.trim_end().len());
+            line
+        };
+
+        let mut header_lines = render_row(&mut header.iter().map(AsRef::as_ref));
+        header_lines.push('\n');
+        let separator: Vec<_> = widths.iter().map(|&width| "-".repeat(width)).collect();
+        header_lines.push_str(&separator.join("-+-"));
+        header_lines.push('\n');
+
+        let lines: Vec<_> = rows.iter().map(|row| render_row(&mut row.iter().map(AsRef::as_ref))).collect();
+        self.add_fenced_lines("", "", &header_lines, lines)
+    }
+
+    /// Adds lines in a code block that's closed and opened again in every message it continues in,
+    /// with `header` put after every opening fence and `prefix`, like `> ` for a quote, at the start of the fences and every line
+    pub(crate) fn add_fenced_lines<I>(&mut self, prefix: &str, lang: &str, header: &str, lines: I) -> &mut Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.end_section();
+        if self.code_blocks_own_message {
+            self.break_message();
+        }
+        let header = if self.sanitize_mentions { sanitize_mentions(header) } else { header.to_owned() };
+        let opening_fence = format!("{}```{}\n{}", prefix, lang, header);
+        let closing_fence = format!("{}```\n", prefix);
+        let fences_size = self.length_mode.len(&opening_fence) + self.length_mode.len(&closing_fence);
+        let continued_fence = format!("{}```{}\n{}{}\n{}", prefix, lang, prefix, continued_comment(lang), header);
+
+        let mut opened = false;
+        // how many lines have been added since the fence was last opened
+        let mut block_lines = 0;
+        for line in lines {
+            let line = line.as_ref();
+            let line = line.strip_suffix('\n').unwrap_or(line);
+            let mut line = format!("{}{}\n", prefix, line);
+            if self.sanitize_mentions {
+                line = sanitize_mentions(&line);
+            }
+            let line_size = self.length_mode.len(&line);
+
+            if !opened {
+                if self.chars_num + fences_size + line_size > self.limit() {
+                    self.new_message();
+                } else if self.last_char().is_some_and(|c| c != '\n') {
+                    self.add_str("\n");
+                }
+                self.add_str(&opening_fence);
+                opened = true;
+            } else {
+                if self.max_code_lines > 0 && block_lines == self.max_code_lines {
+                    self.add_str(&closing_fence);
+                    self.new_message();
+                    self.add_str(&continued_fence);
+                    block_lines = 0;
+                }
+                if block_lines > 0 && self.chars_num + line_size + self.length_mode.len(&closing_fence) > self.limit() {
+                    self.add_str(&closing_fence);
+                    self.new_message();
+                    self.add_str(&opening_fence);
+                    block_lines = 0;
+                }
+            }
+            self.add_str(&line);
+            block_lines += 1;
+        }
+        if !opened {
+            self.add_str(&opening_fence);
+        }
+        self.add_str(&closing_fence);
+
+        if self.code_blocks_own_message {
+            self.break_message();
+        }
+        self
+    }
+
+    /// Adds a progress bar like `[████░░░░] 50%` that's `width` blocks wide, on a line of its own as a section
+    ///
+    /// `fraction` is clamped to be from 0 to 1, and a fraction that isn't a number counts as 0.
+    pub fn add_progress_bar(&mut self, fraction: f32, width: usize) -> &mut Self {
+        let fraction = if fraction.is_nan() { 0. } else { fraction.clamp(0., 1.) };
+        let filled = ((fraction * width as f32).round() as usize).min(width);
+        let bar = format!(
+            "[{}{}] {}%\n",
+            "█".repeat(filled),
+            "░".repeat(width - filled),
+            (fraction * 100.).round(),
+        );
+        self.count_input(None);
+        self.begin_section().add_text(&bar);
+        self.end_section()
+    }
+
+    /// Adds lines padded with spaces on the right to `width` columns in a code block
+    ///
+    /// The code block is split between lines like `add_code_block_lines`. Widths are approximate: characters from East Asian scripts and emoji count as two columns
+    /// and combining marks as none. Lines that are already as wide are left as they are.
+    ///
+    /// Fails without adding anything if `width` is over the limit of the current message.
+    pub fn add_right_aligned(&mut self, lines: &[&str], width: usize) -> Result<&mut Self, BuildError> {
+        let limit = self.limit();
+        if width > limit {
+            return Err(BuildError::WidthTooLarge { width, limit });
+        }
+        self.count_input(lines.iter().copied());
+        let padded_lines = lines.iter().map(|line| {
+            let line_width: usize = line.chars().map(approx_display_width).sum();
+            let mut padded = (*line).to_owned();
+            padded.extend(std::iter::repeat(' ').take(width.saturating_sub(line_width)));
+            padded
+        });
+        Ok(self.add_fenced_lines("", "", "", padded_lines))
+    }
+
+    #[inline]
+    /// Adds a list of users given as pairs of user ids and display names,
+    /// each on their own line as `<@id> (name)`
+    ///
+    /// See `add_user_list_with` for details.
+    pub fn add_user_list(&mut self, users: &[(u64, &str)]) -> &mut Self {
+        self.add_user_list_with(users, UserListOptions::default())
+    }
+
+    /// Adds a list of users given as pairs of user ids and display names, each on their own line
+    ///
+    /// Every user is a separate section so names aren't split, and users appearing more than once are only listed the first time.
+    /// If mentions are sanitized, only the names are affected and the mentions added by this are kept.
+    pub fn add_user_list_with(&mut self, users: &[(u64, &str)], options: UserListOptions) -> &mut Self {
+        let mut seen = HashSet::new();
+        let mut users: Vec<_> = users.iter().filter(|(id, _)| seen.insert(*id)).collect();
+        if options.sort {
+            users.sort_by_cached_key(|(_, name)| name.to_lowercase());
+        }
+
+        for (id, name) in users {
+            self.begin_section();
+            if options.mention {
+                let sanitize = replace(&mut self.sanitize_mentions, false);
+                self.add_text(&format!("<@{}> (", id));
+                self.sanitize_mentions = sanitize;
+                self.add_string(name).add_text(")\n");
+            } else {
+                self.add_string(name).add_text("\n");
+            }
+            self.end_section();
+        }
+
+        self
+    }
+
+    #[inline]
+    /// Finalise the current section if one is in progress
+    /// and return the final `MsgBunch`
+    ///
+    /// # Panics
+    ///
+    /// Panics if building failed, use `try_build` to handle the error instead
+    pub fn build(self) -> MsgBunch {
+        match self.try_build() {
+            Ok(bunch) => bunch,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Finalise the current section if one is in progress
+    /// and return the final `MsgBunch` or the first error that was encountered while building it
+    pub fn try_build(mut self) -> Result<MsgBunch, BuildError> {
+        self.end_section();
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        if !self.trailing_newline {
+            let last = self.inner.messages.last_mut().unwrap();
+            if last.ends_with('\n') {
+                last.pop();
+                if last.is_empty() {
+                    self.chars_num = 0;
+                }
+            }
+        }
+        // A message break at the very end leaves an empty message that shouldn't be sent
+        if self.inner.messages.len() > 1 && self.chars_num == 0 {
+            self.inner.messages.pop();
+        }
+        if let OverflowPolicy::Truncate { max_messages } = self.overflow_policy {
+            self.truncate(max_messages.max(1));
+        }
+        // forgets what was recorded about the messages that were removed
+        let new_index: Vec<_> = (0..self.inner.messages.len()).map(Some).collect();
+        self.inner.remap_indices(&new_index);
+        if self.preserve_spoilers {
+            balance_spoilers(&mut self.inner.messages);
+        }
+        if !self.sticky_prefix.is_empty() {
+            for msg in &mut self.inner.messages {
+                if !msg.is_empty() {
+                    msg.insert_str(0, &self.sticky_prefix);
+                }
+            }
+        }
+        if !self.continued_header.is_empty() {
+            for msg in self.inner.messages.iter_mut().skip(1) {
+                if !msg.is_empty() {
+                    msg.insert_str(0, &self.continued_header);
+                }
+            }
+        }
+        let trailer = self.trailer_message.take();
+        // the trailer takes the place of the only message if there's no content
+        let no_content = self.inner.messages.len() == 1 && self.inner.messages[0].is_empty();
+        if let Some(RemainingFooter(footer)) = &self.remaining_footer {
+            let total = self.inner.messages.len() + usize::from(trailer.is_some() && !no_content);
+            for (i, msg) in self.inner.messages.iter_mut().enumerate().take(total - 1) {
+                msg.push_str(&footer(total - 1 - i));
+            }
+        }
+        if let Some(trailer) = trailer {
+            if no_content {
+                self.inner.messages[0] = trailer;
+            } else {
+                self.inner.messages.push(trailer);
+            }
+        }
+
+        for (index, msg) in self.inner.messages.iter().enumerate() {
+            let len = self.length_mode.len(msg);
+            let limit = self.full_limit_at(index);
+            if len > limit {
+                return Err(BuildError::MessageTooLong { index, len, limit });
+            }
+        }
+        if let Some(Tap(mut tap)) = self.tap.take() {
+            for (index, msg) in self.inner.messages.iter().enumerate() {
+                tap(index, msg);
+            }
+        }
+        Ok(self.inner)
+    }
+
+    /// Finalise the current section if one is in progress and return the content as one message
+    ///
+    /// Fails with `BuildError::NotSingleMessage`, telling how much too long the content is, if it needed more than one message,
+    /// or with any error `try_build` would fail with.
+    /// A trailer message is a message of its own, so with one set there can't be any other content.
+    pub fn build_single(mut self) -> Result<String, BuildError> {
+        self.end_section();
+        let trailer = self.trailer_message.as_deref();
+        let non_empty = self.inner.messages.iter().map(|msg| &**msg).chain(trailer).filter(|msg| !msg.is_empty()).count();
+        if non_empty > 1 {
+            let len: usize = self.inner.messages.iter().map(|msg| &**msg).chain(trailer).map(|msg| self.length_mode.len(msg)).sum();
+            let limit = self.limit_at(0);
+            return Err(BuildError::NotSingleMessage { over: len.saturating_sub(limit), limit });
+        }
+        let mut messages = self.try_build()?.into_inner();
+        Ok(messages.swap_remove(0))
+    }
+
+    /// Same as `build` but `f` decides where to split wherever the builder split content because it didn't fit
+    ///
+    /// The content between any messages started on purpose, e.g. with `break_message`, is split again from the start:
+    /// whenever what's left doesn't fit in a message `f` is given it and the limit and returns the character index to split at.
+    /// If it returns `None`, or an index that's 0 or would put more than the limit in the message,
+    /// the split the builder would have made is used, or a hard split if that doesn't fit anymore.
+    ///
+    /// What the builder put at the start of messages itself, i.e. lines repeated by `overlap_lines` and `section_indent`,
+    /// is left out, as it isn't content.
+    /// Section placements are moved to the messages their first and last message started in.
+    ///
+    /// # Panics
+    ///
+    /// Panics if building failed, like `build`
+    pub fn build_with_breaker<F: FnMut(&str, usize) -> Option<usize>>(mut self, mut f: F) -> MsgBunch {
+        self.end_section();
+        let mut messages = take(&mut self.inner.messages);
+        for (index, len) in take(&mut self.inserted) {
+            messages[index].replace_range(..len, "");
+        }
+        let hard_split_before = take(&mut self.inner.hard_split_before);
+        let mut split_again = Vec::with_capacity(messages.len());
+        let mut new_index = Vec::with_capacity(messages.len());
+        let mut group_start = 0;
+        for group_end in take(&mut self.forced_breaks).into_iter().chain(Some(messages.len())) {
+            let group = &messages[group_start..group_end];
+            let hard = |i: usize| hard_split_before.contains(&(group_start + i));
+            self.split_again(group, hard, &mut split_again, &mut new_index, &mut f);
+            group_start = group_end;
+        }
+        self.chars_num = self.length_mode.len(split_again.last().unwrap());
+        self.inner.messages = split_again;
+        self.inner.remap_indices(&new_index);
+        self.build()
+    }
+
+    /// Splits the content of `messages` again into `out` using `f`, see `build_with_breaker`
+    ///
+    /// `hard` tells whether the message at an index in `messages` was hard split from the one before it.
+    /// The index in `out` that each of `messages` starts in is pushed to `new_index`, and the hard splits to `inner`.
+    fn split_again<H, F>(&mut self, messages: &[String], hard: H, out: &mut Vec<String>, new_index: &mut Vec<Option<usize>>, f: &mut F)
+    where
+        H: Fn(usize) -> bool,
+        F: FnMut(&str, usize) -> Option<usize>,
+    {
+        let text = messages.concat();
+        let first = new_index.len();
+        let starts: Vec<usize> = messages.iter().scan(0, |start, msg| {
+            let msg_start = *start;
+            *start += msg.len();
+            Some(msg_start)
+        }).collect();
+        let mut pos = 0;
+        loop {
+            let rest = &text[pos..];
+            let limit = self.limit_at(out.len());
+            if self.length_mode.len(rest) <= limit {
+                out.push(rest.to_owned());
+                new_index.resize(first + messages.len(), Some(out.len() - 1));
+                break;
+            }
+            let fits = |index: usize| index > 0 && index <= rest.len() && self.length_mode.len(&rest[..index]) <= limit;
+
+            let broken = f(rest, limit)
+                .and_then(|i| rest.char_indices().nth(i).map(|(index, _)| index))
+                .filter(|&index| fits(index));
+            let boundary = starts.iter().position(|&start| start > pos).filter(|&i| fits(starts[i] - pos));
+            let (index, is_hard) = match (broken, boundary) {
+                (Some(index), _) => (index, false),
+                (None, Some(i)) => (starts[i] - pos, hard(i)),
+                (None, None) => (self.hard_split_index(rest, limit), true),
+            };
+
+            out.push(rest[..index].to_owned());
+            pos += index;
+            // every message starting in what was just pushed now starts in it
+            let mapped = new_index.len() - first;
+            new_index.extend(starts[mapped..].iter().take_while(|&&start| start < pos).map(|_| Some(out.len() - 1)));
+            if is_hard {
+                self.inner.hard_split_before.push(out.len());
+            }
+        }
+    }
+
+    /// Same as `build` but also returns the range of line numbers each message contains, starting from 1
+    ///
+    /// Only lines added with `add_lines` are numbered, counting across all calls.
+    /// A message with no such lines gets `None`, and a line split over several messages is in the range of each of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if building failed, like `build`
+    pub fn build_with_line_map(mut self) -> (MsgBunch, Vec<Option<RangeInclusive<usize>>>) {
+        let line_spans = take(&mut self.line_spans);
+        let bunch = self.build();
+
+        let mut line_map: Vec<Option<RangeInclusive<usize>>> = vec![None; bunch.messages.len()];
+        for (line, (first, last)) in line_spans.into_iter().enumerate() {
+            let line = line + 1;
+            for range in line_map.iter_mut().take(last + 1).skip(first) {
+                *range = Some(match range.take() {
+                    Some(range) => *range.start()..=line,
+                    None => line..=line,
+                });
+            }
+        }
+        (bunch, line_map)
+    }
+
+    #[cfg(feature = "stats")]
+    /// Same as `build` but also returns statistics about the building
+    pub fn build_with_stats(self) -> (MsgBunch, BuildStats) {
+        let StatsTracker { started, input_chars, sections } = self.stats;
+        let hard_splits = self.hard_splits;
+        let bunch = self.build();
+
+        let stats = BuildStats {
+            input_chars,
+            output_messages: bunch.messages.len(),
+            hard_splits,
+            sections,
+            elapsed: started.map_or(Duration::ZERO, |started| started.elapsed()),
+        };
+        (bunch, stats)
+    }
+}
+
+/// Inserts a zero-width space into `@everyone`, `@here` and raw user and role mentions so they don't ping anyone
+pub fn sanitize_mentions(s: &str) -> String {
+    s.replace("@everyone", "@\u{200B}everyone")
+        .replace("@here", "@\u{200B}here")
+        .replace("<@", "<@\u{200B}")
+}
+
+#[inline]
+/// Puts a backslash before every character that Discord would read as Markdown, so the text shows up literally
+///
+/// `\`, `*`, `_`, `~`, `` ` ``, `|`, `[` and `]` are escaped everywhere,
+/// and `#`, `>` and `-` when they start a line, where they make headings, quotes and lists.
+pub fn escape_markdown(s: &str) -> String {
+    escape_markdown_from(s, true)
+}
+
+/// Same as `escape_markdown` but `at_line_start` tells whether `s` starts a line
+fn escape_markdown_from(s: &str, mut at_line_start: bool) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        let significant = match c {
+            '\\' | '*' | '_' | '~' | '`' | '|' | '[' | ']' => true,
+            '#' | '>' | '-' => at_line_start,
+            _ => false,
+        };
+        if significant {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        if c == '\n' {
+            at_line_start = true;
+        } else if c != ' ' {
+            at_line_start = false;
+        }
+    }
+    escaped
+}
+
+/// A `(continued)` comment in the syntax of the language `lang`, or as a plain line if it isn't known
+fn continued_comment(lang: &str) -> &'static str {
+    match &*lang.to_lowercase() {
+        "rust" | "rs" | "c" | "cpp" | "c++" | "cs" | "csharp" | "java" | "js" | "javascript" | "ts" | "typescript" | "go" | "swift"
+        | "kotlin" | "kt" | "scala" | "dart" | "php" | "zig" => "// (continued)",
+        "py" | "python" | "sh" | "bash" | "shell" | "zsh" | "rb" | "ruby" | "yaml" | "yml" | "toml" | "perl" | "r" | "ps1"
+        | "powershell" | "dockerfile" | "makefile" | "nix" => "# (continued)",
+        "sql" | "lua" | "hs" | "haskell" => "-- (continued)",
+        "html" | "xml" | "md" | "markdown" => "<!-- (continued) -->",
+        "ini" | "asm" | "lisp" | "clojure" | "clj" => "; (continued)",
+        "tex" | "latex" | "erlang" | "erl" | "matlab" => "% (continued)",
+        "css" => "/* (continued) */",
+        _ => "(continued)",
+    }
+}
+
+/// Removes the Markdown from `s`, see `MsgBunch::with_transcript` for what's removed
+fn strip_markdown(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut in_code_block = false;
+    for line in s.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            stripped.push_str(line);
+            continue;
+        }
+        let mut rest = line;
+        while let Some(after) = [">>> ", "> ", "-# ", "### ", "## ", "# "].iter().find_map(|prefix| rest.strip_prefix(prefix)) {
+            rest = after;
+        }
+        strip_inline_markdown(rest, &mut stripped);
+    }
+    stripped
+}
+
+/// Removes inline Markdown from `s` and puts the result in `out`, see `strip_markdown`
+fn strip_inline_markdown(s: &str, out: &mut String) {
+    let mut prev = None;
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        let mut len = c.len_utf8();
+        let next = rest[len..].chars().next();
+        match c {
+            '\\' if next.is_some_and(|c| c.is_ascii_punctuation()) => {
+                out.push(next.unwrap());
+                len += 1;
+            }
+            '`' => {
+                let fence_len = rest.len() - rest.trim_start_matches('`').len();
+                let fence = &rest[..fence_len];
+                match rest[fence_len..].find(fence) {
+                    Some(end) => {
+                        out.push_str(&rest[fence_len..fence_len + end]);
+                        len = fence_len + end + fence_len;
+                    }
+                    None => {
+                        out.push_str(fence);
+                        len = fence_len;
+                    }
+                }
+            }
+            '*' if next == Some('*') => len += 1,
+            '_' | '~' | '|' if next == Some(c) => len += 1,
+            // a list marker
+            '*' if next == Some(' ') && s[..s.len() - rest.len()].trim_start().is_empty() => out.push(c),
+            '*' => (),
+            '_' if !(prev.is_some_and(char::is_alphanumeric) && next.is_some_and(char::is_alphanumeric)) => (),
+            '[' => {
+                let link = rest[1..].split_once("](").and_then(|(text, after)| after.split_once(')').map(|(url, _)| (text, url)));
+                match link {
+                    Some((text, url)) if !text.contains(']') && !url.contains(char::is_whitespace) => {
+                        strip_inline_markdown(text, out);
+                        out.push_str(" (");
+                        out.push_str(url);
+                        out.push(')');
+                        len = 1 + text.len() + 2 + url.len() + 1;
+                    }
+                    _ => out.push(c),
+                }
+            }
+            '<' if rest[1..].starts_with("http://") || rest[1..].starts_with("https://") => match rest.find('>') {
+                Some(end) if !rest[..end].contains(char::is_whitespace) => {
+                    out.push_str(&rest[1..end]);
+                    len = end + 1;
+                }
+                _ => out.push(c),
+            },
+            c => out.push(c),
+        }
+        prev = rest[..len].chars().next_back();
+        rest = &rest[len..];
+    }
+}
+
+/// Splits a string into front trim text and end_trim
+/// 
+/// If the string only consists of whitespace, all but the end trim will be empty.
+/// If the string has no whitespace surrounding, the trim strings will be empty.
+pub fn split_trim(s: &str) -> (&str, &str, &str) {
+    let end_trim_index = s.rfind(|c: char| !c.is_whitespace()).map(|i| {
+        i + s[i..].chars().next().unwrap().len_utf8()
+    }).unwrap_or(0);
+    
+    let (start, end_trim) = s.split_at(end_trim_index);
+    
+    let front_trim_index = start.find(|c: char| !c.is_whitespace()).unwrap_or(end_trim_index);
+
+    let (front_trim, text) = start.split_at(front_trim_index);
+
+    (front_trim, text, end_trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_markdown, split_trim, AddOutcome, BuildError, LengthMode, MsgBunch, MsgBunchBuilder, OverflowPolicy, OversizedToken, Platform, SectionPlacement, SizeStats, SplitKind, SplitPoint, UserListOptions, MSG_LIMIT};
+    #[test]
+    fn test_split_trim() {
+        assert_eq!(split_trim("hestetest"), ("", "hestetest", ""));
+        assert_eq!(split_trim("   hest  \n\n asdg \t\n"), ("   ", "hest  \n\n asdg", " \t\n"));
+        assert_eq!(split_trim("\n"), ("", "", "\n"));
+        assert_eq!(split_trim(" "), ("", "", " "));
+    }
+
+    #[test]
+    fn test_sticky_prefix() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.sticky_prefix("Narrator: ").add_string("a".repeat(2 * MSG_LIMIT));
+        let msgs = mbb.build().into_inner();
+
+        assert_eq!(msgs.len(), 3);
+        for msg in &msgs {
+            assert!(msg.starts_with("Narrator: "));
+            assert!(msg.chars().count() <= MSG_LIMIT);
+        }
+        assert_eq!(msgs.iter().map(|m| m.len() - "Narrator: ".len()).sum::<usize>(), 2 * MSG_LIMIT);
+    }
+
+    #[test]
+    fn test_split_marker() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.split_marker("<!--split-->")
+            .add_string("first<!--split-->second")
+            .add_string(" still second<!--split--><!--split-->third");
+        assert_eq!(mbb.build().into_inner(), vec!["first", "second still second", "third"]);
+    }
+
+    #[test]
+    fn test_code_block_stats() {
+        let bunch = MsgBunch::dense("```rust\nfn main() {}\n```\ntext ```inline``` ```json\n{}\n``` ```rust\nlet x;\n```", 20);
+        let stats = bunch.code_block_stats();
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats["rust"], 2);
+        assert_eq!(stats["json"], 1);
+        assert_eq!(stats[""], 1);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.overflow_policy(OverflowPolicy::Truncate { max_messages: 2 })
+            .truncation_marker("[cut]")
+            .add_string("a".repeat(3 * MSG_LIMIT));
+        let bunch = mbb.build();
+        assert!(bunch.was_truncated());
+
+        let msgs = bunch.into_inner();
+        assert_eq!(msgs.len(), 2);
+        assert_eq!(msgs[1].chars().count(), MSG_LIMIT);
+        assert!(msgs[1].ends_with("a[cut]"));
+
+        let mut mbb = MsgBunchBuilder::with_limit(5);
+        mbb.overflow_policy(OverflowPolicy::Truncate { max_messages: 1 })
+            .truncation_marker("[truncated]")
+            .add_string("a".repeat(10));
+        assert_eq!(mbb.try_build().unwrap().into_inner(), vec!["[trun"]);
+    }
+
+    #[test]
+    fn test_platform_length_modes() {
+        let mut mbb = MsgBunchBuilder::for_platform(Platform::Telegram);
+        mbb.add_string("😀".repeat(3000));
+        let msgs = mbb.build().into_inner();
+        assert_eq!(msgs.len(), 2);
+        assert_eq!(LengthMode::Utf16.len(&msgs[0]), 4096);
+        assert_eq!(msgs[0].chars().count(), 2048);
+
+        let mut mbb = MsgBunchBuilder::for_platform(Platform::Slack);
+        mbb.add_string("æ".repeat(3000));
+        let msgs = mbb.build().into_inner();
+        assert_eq!(msgs.iter().map(String::len).collect::<Vec<_>>(), vec![4000, 2000]);
+    }
+
+    #[test]
+    fn test_split_on_lines_only_ndjson() {
+        let short = r#"{"level":"info","msg":"ok"}"#;
+        let long = format!(r#"{{"level":"error","msg":"{}"}}"#, "x".repeat(150));
+        let text = format!("{s}\n{s}\n{l}\n{s}\n{s}\n{s}\n", s = short, l = long);
+
+        let msgs = MsgBunch::split_on_lines_only(&text, 64).into_inner();
+        assert!(msgs.iter().all(|m| m.chars().count() <= 64));
+        assert_eq!(msgs.concat(), text);
+
+        let short_line = format!("{}\n", short);
+        assert_eq!(msgs[0], short_line.repeat(2));
+        // only the long line is hard split
+        assert_eq!(msgs[1..4].concat(), format!("{}\n", long));
+        assert_eq!(msgs[4], short_line.repeat(2));
+        assert_eq!(msgs[5], short_line);
+    }
+
+    #[test]
+    fn test_no_split_point() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.begin_section().add_string("a".repeat(MSG_LIMIT + 1)).end_section();
+        match mbb.try_build() {
+            Err(BuildError::NoSplitPoint { section_preview }) => assert_eq!(section_preview, "a".repeat(50)),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_user_list() {
+        let users = [(3, "carl"), (1, "Anna"), (3, "carl"), (2, "@everyone")];
+
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.add_user_list(&users);
+        assert_eq!(mbb.build().into_inner(), vec!["<@3> (carl)\n<@1> (Anna)\n<@2> (@\u{200B}everyone)\n"]);
+
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.sanitize_mentions(false).add_user_list_with(&users, UserListOptions { mention: false, sort: true });
+        assert_eq!(mbb.build().into_inner(), vec!["@everyone\nAnna\ncarl\n"]);
+    }
+
+    #[test]
+    fn test_first_message_limit() {
+        // fits in one message normally
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.first_message_limit(1000).add_string("a".repeat(1500));
+        let msgs = mbb.build().into_inner();
+        assert_eq!(msgs.iter().map(String::len).collect::<Vec<_>>(), vec![1000, 500]);
+
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.first_message_limit(1000)
+            .add_lines(format!("{}\n{}\n{}", "a".repeat(600), "b".repeat(600), "c".repeat(1500)));
+        let msgs = mbb.build().into_inner();
+        assert_eq!(msgs.len(), 3);
+        assert_eq!(msgs[0], format!("{}\n", "a".repeat(600)));
+        assert_eq!(msgs[1], format!("{}\n", "b".repeat(600)));
+        assert_eq!(msgs[2], format!("{}\n", "c".repeat(1500)));
+    }
+
+    #[test]
+    fn test_line_map() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.add_lines(format!("{}\n{}", "a".repeat(1500), "b".repeat(1000)))
+            .add_string("unnumbered\n")
+            .add_lines("c\nd");
+        let (bunch, line_map) = mbb.build_with_line_map();
+        assert_eq!(bunch.into_inner().len(), 2);
+        assert_eq!(line_map, vec![Some(1..=1), Some(2..=4)]);
+    }
+
+    #[cfg(feature = "pulldown-cmark")]
+    #[test]
+    fn test_markdown_events() {
+        let text = "# Title\n\nSome *text* with `code`.\n\n- one\n- two\n  1. nested\n\n> quoted **bold**\n\n```rust\nfn main() {}\n```\n";
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.add_markdown_events(pulldown_cmark::Parser::new(text));
+        assert_eq!(mbb.build().into_inner(), vec![
+            "# Title\n\nSome *text* with `code`.\n\n- one\n- two\n  1. nested\n\n> quoted **bold**\n\n```rust\nfn main() {}\n```\n",
+        ]);
+
+        // code blocks too long for a message keep their fences in every message
+        let text = format!("```rust\n{}```\n", (0..8).map(|i| format!("line {}\n", i)).collect::<String>());
+        let mut mbb = MsgBunchBuilder::with_limit(40);
+        mbb.add_markdown_events(pulldown_cmark::Parser::new(&text));
+        let msgs = mbb.build().into_inner();
+        assert!(msgs.len() > 1);
+        for msg in &msgs {
+            assert!(msg.starts_with("```rust\n") && msg.ends_with("```\n"), "{:?}", msg);
+        }
+    }
+
+    #[cfg(feature = "unicode-segmentation")]
+    #[test]
+    fn test_keep_graphemes() {
+        use super::OversizedGrapheme;
+        let family = "👨‍👩‍👧‍👦";
+        assert_eq!(family.chars().count(), 7);
+
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.keep_graphemes(OversizedGrapheme::Error).add_string(format!("abc{}{}", family, family));
+        assert_eq!(mbb.build().into_inner(), vec![format!("abc{}", family), family.to_owned()]);
+
+        let zwj_sequence = format!("🏳️\u{200D}🌈{}", "\u{200D}👨".repeat(4));
+        let len = zwj_sequence.chars().count();
+
+        let mut mbb = MsgBunchBuilder::with_limit(5);
+        mbb.keep_graphemes(OversizedGrapheme::Split).add_string(&zwj_sequence);
+        let msgs = mbb.build().into_inner();
+        assert_eq!(msgs.concat(), zwj_sequence);
+        assert!(msgs.iter().all(|m| m.chars().count() <= 5));
+
+        let mut mbb = MsgBunchBuilder::with_limit(5);
+        mbb.keep_graphemes(OversizedGrapheme::Error).add_string(&zwj_sequence);
+        assert_eq!(mbb.try_build().unwrap_err(), BuildError::GraphemeTooLong { len, limit: 5 });
+    }
+
+    #[test]
+    fn test_oversized_token_replace() {
+        let blob = "A".repeat(12345);
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.oversized_tokens(OversizedToken::Replace { placeholder: "[content too long, {} chars omitted]".to_owned() })
+            .add_string(format!("data: {}\nend", blob));
+        let bunch = mbb.build();
+        assert_eq!(bunch.omitted(), &[blob][..]);
+        assert_eq!(bunch.into_inner(), vec!["data: [content too long, 12,345 chars omitted]\nend"]);
+    }
+
+    #[test]
+    fn test_content_hash() {
+        // the test vectors of FNV-1a
+        assert_eq!(MsgBunch::dense("", 5).content_hash(), 0xcbf29ce484222325);
+        assert_eq!(MsgBunch::dense("foobar", 5).content_hash(), 0x85944171f73967e8);
+        assert_eq!(MsgBunch::dense("foobar", 2).content_hash(), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn test_code_blocks_own_message() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.code_blocks_own_message(true)
+            .add_string("Some prose\n```rust\nfn main() {}\n")
+            .add_string("```\nMore prose ```inline``` the end");
+        assert_eq!(mbb.build().into_inner(), vec![
+            "Some prose\n",
+            "```rust\nfn main() {}\n```\n",
+            "More prose ",
+            "```inline``` the end",
+        ]);
+    }
+
+    #[test]
+    fn test_section_placements() {
+        let mut mbb = MsgBunchBuilder::with_limit(20);
+        mbb.record_sections(true)
+            .add_lines("short\nthis line, is long. yes\nend");
+        let bunch = mbb.build();
+        assert_eq!(bunch.section_placements(), &[
+            SectionPlacement { section_index: 0, message_index: 0, was_split: false, split_count: 0 },
+            SectionPlacement { section_index: 1, message_index: 1, was_split: true, split_count: 1 },
+            SectionPlacement { section_index: 2, message_index: 2, was_split: false, split_count: 0 },
+        ][..]);
+
+        // sections ended before recording started still count
+        let mut mbb = MsgBunchBuilder::with_limit(20);
+        mbb.add_lines("one\ntwo").record_sections(true).add_lines("three");
+        assert_eq!(mbb.build().section_placements(), &[
+            SectionPlacement { section_index: 2, message_index: 0, was_split: false, split_count: 0 },
+        ][..]);
+    }
+
+    #[test]
+    fn test_overlap_lines() {
+        let mut mbb = MsgBunchBuilder::with_limit(30);
+        mbb.overlap_lines(1).add_lines("line one\nline two\nline three\nline four\nline five");
+        assert_eq!(mbb.build().into_inner(), vec![
+            "line one\nline two\nline three\n",
+            "> line three\nline four\n",
+            "> line four\nline five\n",
+        ]);
+
+        // a line split in the middle isn't repeated
+        let mut mbb = MsgBunchBuilder::with_limit(12);
+        mbb.overlap_lines(1).add_string("aaaa\nbbbb\ncccc\ndddd\n");
+        assert_eq!(mbb.build().into_inner(), vec!["aaaa\nbbbb\ncc", "cc\ndddd\n"]);
+
+        // a line that only fits without the overlap isn't split for it
+        let mut mbb = MsgBunchBuilder::with_limit(30);
+        mbb.overlap_lines(1).add_lines(format!("a\n{}", "b".repeat(28)));
+        assert_eq!(mbb.try_build().unwrap().into_inner(), vec!["a\n".to_owned(), format!("{}\n", "b".repeat(28))]);
+
+        let mut mbb = MsgBunchBuilder::with_limit(30);
+        mbb.overlap_lines(1).add_records(format!("[1] a\n[2] {}\n", "c".repeat(25)), |line| line.starts_with('['));
+        assert_eq!(mbb.try_build().unwrap().into_inner(), vec!["[1] a\n".to_owned(), format!("[2] {}\n", "c".repeat(25))]);
+    }
+
+    #[test]
+    fn test_add_right_aligned() {
+        let mut mbb = MsgBunchBuilder::with_limit(40);
+        mbb.add_right_aligned(&["1. ab", "2. 日本"], 8).unwrap();
+        assert_eq!(mbb.build().into_inner(), vec!["```\n1. ab   \n2. 日本 \n```\n"]);
+
+        let mut mbb = MsgBunchBuilder::with_limit(20);
+        mbb.add_right_aligned(&["aa", "bb", "cc", "dd", "ee"], 5).unwrap();
+        assert_eq!(mbb.build().into_inner(), vec![
+            "```\naa   \nbb   \n```\n",
+            "```\ncc   \ndd   \n```\n",
+            "```\nee   \n```\n",
+        ]);
+
+        let mut mbb = MsgBunchBuilder::with_limit(40);
+        assert_eq!(mbb.add_right_aligned(&["x"], 41).unwrap_err(), BuildError::WidthTooLarge { width: 41, limit: 40 });
+    }
+
+    #[test]
+    fn test_try_add_string() {
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        assert_eq!(mbb.try_add_string("12345"), Ok(AddOutcome::Fit));
+        assert_eq!(mbb.try_add_string("123456789012"), Ok(AddOutcome::HardSplit));
+        mbb.break_message();
+        assert_eq!(mbb.try_add_string("1234"), Ok(AddOutcome::Fit));
+        mbb.begin_section();
+        assert_eq!(mbb.try_add_string("a b c d e f"), Ok(AddOutcome::Fit));
+        mbb.end_section();
+    }
+
+    #[test]
+    fn test_urls_own_line() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.urls_own_line(true)
+            .add_string("see https://a.example and http://b.example\n")
+            .add_string("```\nhttps://c.example here\n```\n");
+        assert_eq!(mbb.build().into_inner(), vec![
+            "see\nhttps://a.example\nand\nhttp://b.example\n```\nhttps://c.example here\n```\n",
+        ]);
+
+        // the space after a URL that ended the previous string isn't kept either
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.urls_own_line(true).add_string("see https://a.example").add_string(" then\n");
+        mbb.add_string("https://b.example").add_string(" \n").add_string("  ").add_string(" \tnext");
+        assert_eq!(mbb.build().into_inner(), vec!["see\nhttps://a.example\nthen\nhttps://b.example\nnext"]);
+    }
+
+    #[test]
+    fn test_soft_wrap() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.soft_wrap(10)
+            .add_string("aaa bbb ccc ddd\nshort\n")
+            .add_string("```\nthis line is in a code block\n```\n")
+            .add_string("eee fff")
+            .add_string(" ggg");
+        assert_eq!(mbb.build().into_inner(), vec![
+            "aaa bbb\nccc ddd\nshort\n```\nthis line is in a code block\n```\neee fff\nggg",
+        ]);
+    }
+
+    #[test]
+    fn test_contains_across() {
+        let bunch = MsgBunch::dense("abcdefghij", 4);
+        assert_eq!(bunch.count_containing("ef"), 1);
+        assert_eq!(bunch.count_containing("de"), 0);
+        assert!(bunch.contains_across("de"));
+        assert!(!bunch.contains_across("dd"));
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn test_send_with() {
+        use std::future::Future;
+        use std::pin::pin;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct NoopWaker;
+        impl Wake for NoopWaker {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        let bunch = MsgBunch::dense("abcdef", 2);
+        let mut sent = Vec::new();
+        {
+            let future = pin!(bunch.send_with(|msg| {
+                sent.push(msg.to_owned());
+                let result = if sent.len() < 2 { Ok(()) } else { Err(sent.len()) };
+                async move { result }
+            }));
+            let polled = future.poll(&mut Context::from_waker(&Waker::from(Arc::new(NoopWaker))));
+            assert_eq!(polled, Poll::Ready(Err(2)));
+        }
+        assert_eq!(sent, vec!["ab", "cd"]);
+    }
+
+    #[test]
+    fn test_continued_header() {
+        let mut mbb = MsgBunchBuilder::with_limit(20);
+        mbb.continued_header("(cont.)\n").add_string("a".repeat(44));
+        let msgs = mbb.build().into_inner();
+        assert_eq!(msgs, vec![
+            "a".repeat(20),
+            format!("(cont.)\n{}", "a".repeat(12)),
+            format!("(cont.)\n{}", "a".repeat(12)),
+        ]);
+        assert!(!msgs[0].starts_with("(cont.)"));
+    }
+
+    #[cfg(feature = "unicode-width")]
+    #[test]
+    fn test_display_width() {
+        assert_eq!(LengthMode::DisplayWidth.len("ab日本"), 6);
+
+        let mut mbb = MsgBunchBuilder::with_limit(4);
+        mbb.length_mode(LengthMode::DisplayWidth).add_string("日本語ab");
+        assert_eq!(mbb.build().into_inner(), vec!["日本", "語ab"]);
+    }
+
+    #[test]
+    fn test_prefer_blank_line_breaks() {
+        let mut mbb = MsgBunchBuilder::with_limit(20);
+        mbb.prefer_blank_line_breaks(true).add_string("one two\n\nthree four five six");
+        assert_eq!(mbb.build().into_inner(), vec!["one two\n\n", "three four five six"]);
+
+        let mut mbb = MsgBunchBuilder::with_limit(20);
+        mbb.prefer_blank_line_breaks(true).add_string("one two three four five six");
+        assert_eq!(mbb.build().into_inner(), vec!["one two three four ", "five six"]);
+    }
+
+    #[test]
+    fn test_strict_sections() {
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.strict_sections(true).add_lines("short\nthis, one, is, long\n");
+        assert_eq!(mbb.try_build().unwrap_err(), BuildError::SectionSplit {
+            section_index: 1,
+            section_preview: "this, one, is, long\n".to_owned(),
+        });
+    }
+
+    #[test]
+    fn test_add_code_block_lines() {
+        let mut mbb = MsgBunchBuilder::with_limit(30);
+        mbb.add_string("Log:").add_code_block_lines("rs", (1..=4).map(|i| format!("line {}", i)));
+        assert_eq!(mbb.build().into_inner(), vec![
+            "Log:\n```rs\nline 1\nline 2\n```\n",
+            "```rs\nline 3\nline 4\n```\n",
+        ]);
+    }
+
+    #[test]
+    fn test_closing_bracket_not_orphaned() {
+        let mut mbb = MsgBunchBuilder::with_limit(25);
+        mbb.begin_section().add_string("Hi: it went well (mostly.) Bye").end_section();
+        assert_eq!(mbb.build().into_inner(), vec!["Hi:", " it went well (mostly.)", " Bye"]);
+    }
+
+    #[test]
+    fn test_count_for_limits() {
+        let text = "a".repeat(4500);
+        assert_eq!(MsgBunch::count_for_limits(&text, &[1500, 2000, 5000]), vec![3, 3, 1]);
+        assert_eq!(MsgBunch::count_for_limits("", &[10]), vec![MsgBunch::dense("", 10).iter().count()]);
+
+        // splitting between lines can take more messages than the lower bound
+        let mut mbb = MsgBunchBuilder::with_limit(12);
+        mbb.add_lines("aaaaaa\nbbbbbb\ncccccc");
+        assert_eq!(mbb.build().iter().count(), 3);
+        assert_eq!(MsgBunch::count_for_limits("aaaaaa\nbbbbbb\ncccccc\n", &[12]), vec![2]);
+    }
+
+    #[test]
+    fn test_add_table() {
+        let rows: Vec<_> = (1..=6).map(|i| vec![i.to_string(), format!("player{}", i)]).collect();
+        let mut mbb = MsgBunchBuilder::with_limit(60);
+        mbb.add_table(&["#", "Name"], &rows);
+        let msgs = mbb.build().into_inner();
+        assert_eq!(msgs, vec![
+            "```\n# | Name\n--+--------\n1 | player1\n2 | player2\n```\n",
+            "```\n# | Name\n--+--------\n3 | player3\n4 | player4\n```\n",
+            "```\n# | Name\n--+--------\n5 | player5\n6 | player6\n```\n",
+        ]);
+    }
+
+    #[test]
+    fn test_trailing_newline() {
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.trailing_newline(false).add_lines("aaaa\nbbbb\ncccc");
+        assert_eq!(mbb.build().into_inner(), vec!["aaaa\nbbbb\n", "cccc"]);
+    }
+
+    #[test]
+    fn test_source_round_trip() {
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.add_lines("one\ntwo\nthree").add_string("aaaaaaaaaaaaaaa");
+        let bunch = mbb.build();
+        let messages = bunch.clone().into_inner();
+
+        let (source, split_points) = bunch.into_source();
+        assert_eq!(split_points, vec![
+            SplitPoint { offset: 8, kind: SplitKind::Nice },
+            SplitPoint { offset: 18, kind: SplitKind::Hard },
+            SplitPoint { offset: 28, kind: SplitKind::Hard },
+        ]);
+        assert_eq!(MsgBunch::from_source(&source, &split_points, 10, LengthMode::Chars).into_inner(), messages);
+
+        // the splits of the content that was cut off aren't kept, so the trailer isn't taken to be hard split from it
+        let mut mbb = MsgBunchBuilder::with_limit(4);
+        mbb.overflow_policy(OverflowPolicy::Truncate { max_messages: 1 }).trailer_message("end".to_owned()).add_string("aaaaaaaaaa");
+        assert_eq!(mbb.build().into_source(), ("aaa…end".to_owned(), vec![SplitPoint { offset: 6, kind: SplitKind::Nice }]));
+    }
+
+    #[test]
+    fn test_dense() {
+        assert_eq!(MsgBunch::dense("abcdef", 3).into_inner(), vec!["abc", "def"]);
+        assert_eq!(MsgBunch::dense("abcdefgh", 3).into_inner(), vec!["abc", "def", "gh"]);
+        assert_eq!(MsgBunch::dense("æøåæø", 2).into_inner(), vec!["æø", "åæ", "ø"]);
+        assert_eq!(MsgBunch::dense("", 3).into_inner(), vec![""]);
+
+        let (_, split_points) = MsgBunch::dense("abcdefgh", 3).into_source();
+        assert_eq!(split_points, vec![SplitPoint { offset: 3, kind: SplitKind::Hard }, SplitPoint { offset: 6, kind: SplitKind::Hard }]);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let mut mbb = MsgBunchBuilder::with_limit(6);
+        mbb.record_sections(true).begin_named_section("first").add_string("ab").end_section().break_message().add_string("cd");
+        let mut bunch = mbb.build();
+        for msg in bunch.iter_mut() {
+            msg.push_str("xxxx");
+        }
+        bunch.normalize();
+        assert_eq!(bunch.section_placements(), &[][..]);
+        assert_eq!(bunch.section_message("first"), None);
+        assert_eq!(bunch.clone().into_inner(), vec!["abxxxx", "cdxxxx"]);
+        assert_eq!(bunch.into_source().1, vec![SplitPoint { offset: 6, kind: SplitKind::Hard }]);
+
+        let mut bunch = MsgBunch::dense("abcd", 4);
+        bunch.iter_mut().next().unwrap().push_str("efghij");
+        bunch.normalize();
+        assert_eq!(bunch.into_inner(), vec!["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn test_interleave_split_kinds() {
+        let a = MsgBunch::split_on_lines_only("hi\n", 10);
+        let b = MsgBunch::split_on_lines_only("abcdefghij\n", 11);
+        let (source, split_points) = MsgBunch::interleave_labeled(a, b, "", "> ").into_source();
+        assert_eq!(source, "hi\n> abcdefgh> ij\n");
+        assert_eq!(split_points, vec![SplitPoint { offset: 3, kind: SplitKind::Nice }, SplitPoint { offset: 13, kind: SplitKind::Hard }]);
+    }
+
+    #[test]
+    fn test_absorb_short_tail() {
+        let mut bunch = MsgBunch::split_on_lines_only("aaaa\nbbbbbbbb\nc\n", 10);
+        assert_eq!(bunch.iter().count(), 3);
+        bunch.absorb_short_tail(2);
+        assert_eq!(bunch.clone().into_inner(), vec!["aaaa\n", "bbbbbbbb\n", "c\n"]);
+        bunch.absorb_short_tail(3);
+        assert_eq!(bunch.clone().into_inner(), vec!["aaaa\n", "bbbbbbbb\n", "c\n"]);
+
+        let mut bunch = MsgBunch::split_on_lines_only("aaaa\nbbbbbbbb\nc\n", 11);
+        bunch.absorb_short_tail(3);
+        assert_eq!(bunch.into_inner(), vec!["aaaa\n", "bbbbbbbb\nc\n"]);
+
+        let mut bunch = MsgBunch::dense("ab", 5);
+        bunch.absorb_short_tail(10);
+        assert_eq!(bunch.into_inner(), vec!["ab"]);
+
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.add_string("aaaaaaaaaaab").break_message().begin_named_section("tail").add_string("c").end_section();
+        let mut bunch = mbb.build();
+        assert_eq!(bunch.section_message("tail"), Some(2));
+        bunch.absorb_short_tail(2);
+        assert_eq!(bunch.section_message("tail"), Some(1));
+        let (source, split_points) = bunch.into_source();
+        assert_eq!(source, "aaaaaaaaaaabc");
+        assert_eq!(split_points, vec![SplitPoint { offset: 10, kind: SplitKind::Hard }]);
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut bunch = MsgBunch::dense("abcdefgh", 3);
+        assert_eq!(bunch.drain(..1).collect::<Vec<_>>(), vec!["abc"]);
+        assert_eq!(bunch.clone().into_source(), ("defgh".to_owned(), vec![SplitPoint { offset: 3, kind: SplitKind::Hard }]));
+        assert_eq!(bunch.drain(..).collect::<Vec<_>>(), vec!["def", "gh"]);
+        assert_eq!(bunch.iter().count(), 0);
+
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.add_string("one").break_message().add_string("two").break_message().begin_named_section("end").add_string("three").end_section();
+        let mut bunch = mbb.build();
+        assert_eq!(bunch.drain(..2).collect::<Vec<_>>(), vec!["one", "two"]);
+        assert_eq!(bunch.section_message("end"), Some(0));
+        assert_eq!(bunch.into_inner(), vec!["three"]);
+    }
+
+    #[test]
+    fn test_remaining_footer() {
+        let mut mbb = MsgBunchBuilder::with_limit(20);
+        mbb.remaining_footer(|n| format!("\n({} more)", n)).add_string("a".repeat(21));
+        let msgs = mbb.build().into_inner();
+        assert_eq!(msgs, vec![
+            format!("{}\n(2 more)", "a".repeat(9)),
+            format!("{}\n(1 more)", "a".repeat(9)),
+            "aaa".to_owned(),
+        ]);
+    }
+
+    #[test]
+    fn test_escapes_not_split() {
+        let text = "\\*".repeat(20);
+        for limit in 5..12 {
+            let mut mbb = MsgBunchBuilder::with_limit(limit);
+            mbb.add_string(&text);
+            let msgs = mbb.build().into_inner();
+            assert_eq!(msgs.concat(), text);
+            for msg in &msgs {
+                assert!(msg.starts_with('\\'), "{:?} starts with an unescaped character", msg);
+                assert!(msg.chars().count() <= limit);
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_lines_per_message() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.max_lines_per_message(2).add_lines("a\nb\nc\nd\ne").add_string("f\ng\nh");
+        assert_eq!(mbb.build().into_inner(), vec!["a\nb\n", "c\nd\n", "e\nf\n", "g\nh"]);
+
+        let mut mbb = MsgBunchBuilder::with_limit(5);
+        mbb.max_lines_per_message(2).add_string("a\nbbbbbbb\nc\nd");
+        assert_eq!(mbb.build().into_inner(), vec!["a\nbbb", "bbbb\n", "c\nd"]);
+    }
+
+    #[test]
+    fn test_break_before_headings() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.break_before_headings(true).add_lines("intro\n# Rules\n#1 no spam\n#general is for chat\n## More\n###\n### Last");
+        assert_eq!(mbb.build().into_inner(), vec![
+            "intro\n",
+            "# Rules\n#1 no spam\n#general is for chat\n",
+            "## More\n###\n",
+            "### Last\n",
+        ]);
+    }
+
+    #[test]
+    fn test_interleave_labeled() {
+        let a = MsgBunch::split_on_lines_only("hello\nthere\n", 10);
+        let b = MsgBunch::dense("general kenobi, you are a bold one", 40);
+        let bunch = MsgBunch::interleave_labeled(a, b, "A: ", "B: ");
+        assert_eq!(bunch.limit(), 10);
+        assert_eq!(bunch.into_inner(), vec![
+            "A: hello\n",
+            "B: general",
+            "B:  kenobi",
+            "B: , you a",
+            "B: re a bo",
+            "B: ld one",
+            "A: there\n",
+        ]);
+
+        let bunch = MsgBunch::interleave(MsgBunch::dense("abc", 3), MsgBunch::dense("1234567", 3));
+        assert_eq!(bunch.into_inner(), vec!["abc", "123", "456", "7"]);
+
+        // a label as long as the limit is cut short
+        let bunch = MsgBunch::interleave_labeled(MsgBunch::dense("ab", 5), MsgBunch::dense("c", 5), "Alice", "B> ");
+        assert_eq!(bunch.into_inner(), vec!["Alica", "Alicb", "B> c"]);
+    }
+
+    #[test]
+    fn test_named_sections() {
+        let mut mbb = MsgBunchBuilder::with_limit(12);
+        mbb.begin_named_section("intro").add_string("Hello there").end_section()
+            .begin_named_section("rules").add_string("No spam.").end_section();
+        let bunch = mbb.build();
+        assert_eq!(bunch.section_message("intro"), Some(0));
+        assert_eq!(bunch.section_message("rules"), Some(1));
+        assert_eq!(bunch.section_message("faq"), None);
+    }
+
+    #[test]
+    fn test_build_single() {
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.add_string("short");
+        assert_eq!(mbb.build_single(), Ok("short".to_owned()));
+
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.add_string("thirteen long");
+        assert_eq!(mbb.build_single(), Err(BuildError::NotSingleMessage { over: 3, limit: 10 }));
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.normalize_whitespace(true)
+            .add_string("  too   many\t spaces  \n")
+            .add_string("keep `a   b`")
+            .add_string("  and  ")
+            .add_string(" this\n```\n  code   stays\n```\n");
+        assert_eq!(mbb.build().into_inner(), vec!["too many spaces\nkeep `a   b` and this\n```\n  code   stays\n```\n"]);
+    }
+
+    #[test]
+    fn test_chunk_sentences() {
+        let text = "One. Two? Three! Four. Five";
+        assert_eq!(MsgBunch::chunk_sentences(text, 2, 100).into_inner(), vec!["One. Two?", "Three! Four.", "Five"]);
+        assert_eq!(MsgBunch::chunk_sentences(text, 3, 12).into_inner(), vec!["One. Two? ", "Three!", "Four. Five"]);
+
+        // groups longer than the limit are split at whitespace, or hard split without any
+        let text = format!("{} b", "word ".repeat(10));
+        let msgs = MsgBunch::chunk_sentences(&text, 1, 12).into_inner();
+        assert_eq!(msgs[0], "word word ");
+        assert_eq!(msgs.concat(), text);
+        let text = format!("{}. short.", "a".repeat(30));
+        assert_eq!(MsgBunch::chunk_sentences(&text, 1, 10).into_inner(), vec!["a".repeat(10), "a".repeat(10), "a".repeat(10), ".".to_owned(), "short.".to_owned()]);
+    }
+
+    #[test]
+    fn test_message_keys() {
+        let keys = MsgBunch::dense("abcdab", 2).message_keys();
+        assert_eq!(keys[0], keys[2]);
+        assert_ne!(keys[0], keys[1]);
+        assert_eq!(keys[0], MsgBunch::dense("ab", 2).content_hash());
+    }
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(escape_markdown("# *hi* a-b\n> `x`"), "\\# \\*hi\\* a-b\n\\> \\`x\\`");
+
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.plain_text(true).code_blocks_own_message(true).add_string("a\n").add_string("```\n**b**\n```");
+        assert_eq!(mbb.build().into_inner(), vec!["a\n\\`\\`\\`\n\\*\\*b\\*\\*\n\\`\\`\\`"]);
+    }
+
+    #[test]
+    fn test_build_with_breaker() {
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.add_string("aaaa bbbb cccc dddd").break_message().add_string("eeee");
+        let msgs = mbb.build_with_breaker(|rest, _| rest.find(' ').map(|i| i + 1)).into_inner();
+        assert_eq!(msgs, vec!["aaaa ", "bbbb ", "cccc dddd", "eeee"]);
+
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.add_string("aaaa bbbb cccc dddd");
+        assert_eq!(mbb.build_with_breaker(|_, _| None).into_inner(), vec!["aaaa bbbb ", "cccc dddd"]);
+
+        // which splits are hard is known again afterwards
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.add_string("aaaaaaaaaaaa bbbb cc dd");
+        let (source, split_points) = mbb.build_with_breaker(|rest, _| rest.find(' ').map(|i| i + 1)).into_source();
+        assert_eq!(source, "aaaaaaaaaaaa bbbb cc dd");
+        assert_eq!(split_points, vec![
+            SplitPoint { offset: 10, kind: SplitKind::Hard },
+            SplitPoint { offset: 13, kind: SplitKind::Nice },
+        ]);
+
+        // a hard split doesn't separate an escape
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.add_string("aaaaaaaaa\\bbbb");
+        assert_eq!(mbb.build_with_breaker(|_, _| None).into_inner(), vec!["aaaaaaaaa", "\\bbbb"]);
+
+        // repeated lines aren't split again as content
+        let mut mbb = MsgBunchBuilder::with_limit(12);
+        mbb.overlap_lines(1).add_lines("aaaa\nbbbb\ncccc");
+        assert_eq!(mbb.build_with_breaker(|_, _| None).into_inner(), vec!["aaaa\nbbbb\n", "cccc\n"]);
+
+        // section placements follow the content
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.record_sections(true).add_string("aaaa ");
+        mbb.begin_section().add_string("bbbb\ncccc\ndddd").end_section_with(|c| c == '\n');
+        let bunch = mbb.build_with_breaker(|rest, _| rest.find('\n').map(|i| i + 1));
+        assert_eq!(bunch.section_placements(), &[SectionPlacement { section_index: 0, message_index: 0, was_split: true, split_count: 1 }]);
+        assert_eq!(bunch.into_inner(), vec!["aaaa bbbb\n", "cccc\ndddd"]);
+    }
+
+    #[test]
+    fn test_code_file() {
+        let msgs = MsgBunch::code_file("src/main.rs", "fn main() {\n    run();\n}\n", 32).into_inner();
+        assert_eq!(msgs, vec!["📄 main.rs\n```rs\nfn main() {\n```\n", "```rs\n    run();\n}\n```\n"]);
+
+        let msgs = MsgBunch::code_file("/home/me/.bashrc", "ls\n", 100).into_inner();
+        assert_eq!(msgs, vec!["📄 .bashrc\n```\nls\n```\n"]);
+        let msgs = MsgBunch::code_file(".config.YML", "a: 1\n", 100).into_inner();
+        assert_eq!(msgs, vec!["📄 .config.YML\n```yaml\na: 1\n```\n"]);
+    }
+
+    #[test]
+    fn test_changed_indices() {
+        let previous = MsgBunch::dense("aabbcc", 2);
+        assert_eq!(MsgBunch::dense("aaxxccdd", 2).changed_indices(&previous), vec![1, 3]);
+        assert_eq!(MsgBunch::dense("aa", 2).changed_indices(&previous), vec![1, 2]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_to_json_array() {
+        let mut mbb = MsgBunchBuilder::with_limit(4);
+        mbb.add_string("abcdef");
+        assert_eq!(mbb.build().to_json_array(), serde_json::json!(["abcd", "ef"]));
+        assert_eq!(MsgBunch::default().to_json_array(), serde_json::json!([]));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_to_embed_fields() {
+        use crate::limits::{EMBED_FIELD_NAME_LIMIT, EMBED_FIELD_VALUE_LIMIT};
+
+        let line = format!("{}\n", "a".repeat(499));
+        let long_line = "b".repeat(EMBED_FIELD_VALUE_LIMIT + 10);
+        // one message, much longer than a field value
+        let text = format!("{}{}{}{}", line, line, line, long_line);
+        let bunch = MsgBunch::from_source(&text, &[], MSG_LIMIT, LengthMode::Chars);
+
+        let fields = bunch.to_embed_fields(|i| if i == 0 { "n".repeat(EMBED_FIELD_NAME_LIMIT + 1) } else { format!("Part {}", i + 1) });
+        assert_eq!(fields, vec![
+            serde_json::json!({ "name": "n".repeat(EMBED_FIELD_NAME_LIMIT), "value": format!("{}{}", line, line), "inline": false }),
+            serde_json::json!({ "name": "Part 2", "value": line, "inline": false }),
+            serde_json::json!({ "name": "Part 3", "value": long_line[..EMBED_FIELD_VALUE_LIMIT], "inline": false }),
+            serde_json::json!({ "name": "Part 4", "value": long_line[EMBED_FIELD_VALUE_LIMIT..], "inline": false }),
+        ]);
+        assert!(MsgBunch::default().to_embed_fields(|i| i.to_string()).is_empty());
+    }
+
+    #[cfg(feature = "pulldown-cmark")]
+    #[test]
+    fn test_markdown_nested_list_indent() {
+        let text = "- top\n  - middle\n    - deep item, that goes on and on\n";
+        let mut mbb = MsgBunchBuilder::with_limit(30);
+        mbb.add_markdown_events(pulldown_cmark::Parser::new(text));
+        assert_eq!(mbb.build().into_inner(), vec![
+            "- top\n  - middle\n",
+            "    - deep item,",
+            "      that goes on and on\n",
+        ]);
+
+        // the indentation isn't split again as content
+        let mut mbb = MsgBunchBuilder::with_limit(30);
+        mbb.add_markdown_events(pulldown_cmark::Parser::new(text));
+        assert_eq!(mbb.build_with_breaker(|_, _| None).into_inner(), vec![
+            "- top\n  - middle\n",
+            "    - deep item,",
+            "that goes on and on\n",
+        ]);
+    }
+
+    #[test]
+    fn test_size_stats() {
+        assert_eq!(MsgBunch::dense("aaaaaaa", 3).size_stats(), SizeStats { min: 1, max: 3, mean: 7. / 3., count: 3 });
+        assert_eq!(MsgBunch::default().size_stats(), SizeStats { min: 0, max: 0, mean: 0., count: 0 });
+    }
+
+    #[test]
+    fn test_join_dedup_overlap() {
+        let text = "line one\nline two\nline three\nline four\nline five\n";
+        let mut mbb = MsgBunchBuilder::with_limit(30);
+        mbb.overlap_lines(2).add_lines(text);
+        let bunch = mbb.build();
+        assert!(bunch.iter().count() > 1);
+        assert_eq!(bunch.join_dedup_overlap(2), text);
+    }
+
+    #[test]
+    fn test_add_progress_bar() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.add_progress_bar(0.5, 8).add_progress_bar(1.5, 4).add_progress_bar(f32::NAN, 2);
+        assert_eq!(mbb.build().into_inner(), vec!["[████░░░░] 50%\n[████] 100%\n[░░] 0%\n"]);
+    }
+
+    #[test]
+    fn test_custom_emoji_not_split() {
+        let emoji = "<:blob:123456>";
+        for limit in 15..20 {
+            let mut mbb = MsgBunchBuilder::with_limit(limit);
+            mbb.add_string(format!("hi {} {} <a:dance:42>", emoji, emoji));
+            let msgs = mbb.build().into_inner();
+            assert!(msgs.iter().all(|msg| msg.matches('<').count() == msg.matches('>').count()), "{:?}", msgs);
+
+            let mut mbb = MsgBunchBuilder::with_limit(limit + 2);
+            mbb.begin_section().add_string(format!("hi: {}: {}", emoji, emoji)).end_section();
+            let msgs = mbb.build().into_inner();
+            assert!(msgs.iter().all(|msg| msg.matches('<').count() == msg.matches('>').count()), "{:?}", msgs);
+        }
+    }
+
+    #[test]
+    fn test_add_records() {
+        let log = "[1] start\n  detail\n[2] next\n  more detail\n[3] end\n";
+        let mut mbb = MsgBunchBuilder::with_limit(32);
+        mbb.add_records(log, |line| line.starts_with('['));
+        assert_eq!(mbb.build().into_inner(), vec!["[1] start\n  detail\n", "[2] next\n  more detail\n[3] end\n"]);
+
+        // a record too long for a smaller first message is hard split
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.first_message_limit(5).add_records("abcdefgh\n[2] x\n", |line| line.starts_with('['));
+        assert_eq!(mbb.try_build().unwrap().into_inner(), vec!["abcde", "fgh\n[2] x\n"]);
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut mbb = MsgBunchBuilder::with_limit(2);
+        mbb.add_string("æøå").break_message();
+        let bunch = mbb.build();
+        assert_eq!(bunch.per_message_bytes(), vec![4, 2]);
+        assert_eq!(bunch.total_bytes(), 6);
+    }
+
+    #[test]
+    fn test_trailer_message() {
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.trailer_message("Use the buttons".to_owned());
+        assert_eq!(mbb.try_build().unwrap_err(), BuildError::MessageTooLong { index: 0, len: 15, limit: 10 });
+
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.trailer_message("The end".to_owned());
+        assert_eq!(mbb.build().into_inner(), vec!["The end"]);
+
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.trailer_message("The end".to_owned()).add_string("Content").break_message();
+        assert_eq!(mbb.build().into_inner(), vec!["Content", "The end"]);
+
+        let mut mbb = MsgBunchBuilder::with_limit(20);
+        mbb.trailer_message("The end".to_owned()).remaining_footer(|n| format!("\n({} more)", n)).add_string("a".repeat(21));
+        assert_eq!(mbb.build().into_inner(), vec![
+            format!("{}\n(3 more)", "a".repeat(9)),
+            format!("{}\n(2 more)", "a".repeat(9)),
+            "aaa\n(1 more)".to_owned(),
+            "The end".to_owned(),
+        ]);
+
+        let mut mbb = MsgBunchBuilder::with_limit(20);
+        mbb.trailer_message("The end".to_owned()).add_string("Content");
+        assert_eq!(mbb.build_single(), Err(BuildError::NotSingleMessage { over: 0, limit: 20 }));
+
+        let mut mbb = MsgBunchBuilder::with_limit(20);
+        mbb.trailer_message("The end".to_owned());
+        assert_eq!(mbb.build_single(), Ok("The end".to_owned()));
+    }
+
+    #[test]
+    fn test_atomic_substrings() {
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.atomic_substrings(vec!["SKU-1234".to_owned()]).add_string("order SKU-1234 now");
+        assert_eq!(mbb.build().into_inner(), vec!["order ", "SKU-1234 n", "ow"]);
+
+        let mut mbb = MsgBunchBuilder::with_limit(4);
+        mbb.atomic_substrings(vec!["SKU-1234".to_owned()]).add_string("SKU-1234");
+        assert_eq!(mbb.build().into_inner(), vec!["SKU-", "1234"]);
+    }
+
+    #[test]
+    fn test_first_message_whole_sentences() {
+        let mut mbb = MsgBunchBuilder::with_limit(20);
+        mbb.first_message_whole_sentences(true).add_string("It begins. Then it goes on and on");
+        assert_eq!(mbb.build().into_inner(), vec!["It begins.", " Then it goes on and", " on"]);
+    }
+
+    #[test]
+    fn test_coalesce_to() {
+        let mut mbb = MsgBunchBuilder::with_limit(6);
+        mbb.add_string("aa").break_message().add_string("bb").break_message().add_string("cc").break_message().add_string("dd");
+        let mut bunch = mbb.build();
+        assert_eq!(bunch.coalesce_to(1), Err(2));
+        assert_eq!(bunch.iter().count(), 4);
+        assert_eq!(bunch.coalesce_to(3), Ok(()));
+        assert_eq!(bunch.into_inner(), vec!["aabb", "cc", "dd"]);
+
+        let mut mbb = MsgBunchBuilder::with_limit(4);
+        mbb.add_string("aaaaa").break_message().begin_named_section("c").add_string("cc").end_section();
+        let mut bunch = mbb.build();
+        assert_eq!(bunch.section_message("c"), Some(2));
+        assert_eq!(bunch.coalesce_to(2), Ok(()));
+        assert_eq!(bunch.section_message("c"), Some(1));
+        assert_eq!(bunch.into_source(), ("aaaaacc".to_owned(), vec![SplitPoint { offset: 4, kind: SplitKind::Hard }]));
+    }
+
+    #[test]
+    fn test_tap() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let tapped = Rc::clone(&log);
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.sticky_prefix("> ").tap(move |i, msg| tapped.borrow_mut().push(format!("{}:{}", i, msg))).add_string("aaaaaaa\nbbbb");
+        let bunch = mbb.build();
+        assert_eq!(*log.borrow(), vec!["0:> aaaaaaa\n", "1:> bbbb"]);
+        assert_eq!(bunch.into_inner(), vec!["> aaaaaaa\n", "> bbbb"]);
+    }
+
+    #[test]
+    fn test_from_delimited_keep() {
+        let bunch = MsgBunch::from_delimited_keep("one;;two;;three;;", ";;", 10);
+        assert_eq!(bunch.into_inner(), vec!["one;;two;;", "three;;"]);
+
+        let (_, split_points) = MsgBunch::split_on_lines_only("ab\ncdefgh\nij\n", 4).into_source();
+        assert_eq!(split_points, vec![
+            SplitPoint { offset: 3, kind: SplitKind::Nice },
+            SplitPoint { offset: 7, kind: SplitKind::Hard },
+            SplitPoint { offset: 10, kind: SplitKind::Nice },
+        ]);
+    }
+
+    #[test]
+    fn test_preserve_spoilers() {
+        let mut mbb = MsgBunchBuilder::with_limit(12);
+        mbb.preserve_spoilers(true).begin_section().add_string("||aaaa bbbb||").end_section_with(char::is_whitespace);
+        assert_eq!(mbb.build().into_inner(), vec!["||aaaa ||", "||bbbb||"]);
+
+        // adjacent spoilers are two closed spoilers, so nothing needs closing at the boundary
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.preserve_spoilers(true).begin_section().add_string("||a|| ||b||").end_section_with(char::is_whitespace);
+        assert_eq!(mbb.build().into_inner(), vec!["||a|| ", "||b||"]);
+
+        // an empty spoiler straddling the boundary isn't split between its bars nor left open
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.preserve_spoilers(true).add_string("xxxx||||yyyy");
+        assert_eq!(mbb.build().into_inner(), vec!["xxxx", "||||yyyy"]);
+        let mut mbb = MsgBunchBuilder::with_limit(8);
+        mbb.preserve_spoilers(true).add_string("xxx||||yyy");
+        assert_eq!(mbb.build().into_inner(), vec!["xxx", "||||", "yyy"]);
+    }
+
+    #[test]
+    fn test_preserve_discord_tokens() {
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.sanitize_mentions(false).add_string("hey <@&12345> <t:1234:R>");
+        assert_eq!(mbb.build().into_inner()[0], "hey <@&123");
+
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.sanitize_mentions(false).preserve_discord_tokens(true).add_string("hey <@&12345> <t:1234:R>");
+        assert_eq!(mbb.build().into_inner(), vec!["hey ", "<@&12345> ", "<t:1234:R>"]);
+    }
+
+    #[test]
+    fn test_with_transcript() {
+        let mut mbb = MsgBunchBuilder::with_limit(80);
+        mbb.add_lines("# Release notes\n> **big** ~~old~~ change in my_crate\n```rust\nlet x = **y;\n```\n- see [docs](https://example.com) or <https://example.org> \\*now\\*, `a*b`");
+        let bunch = mbb.build();
+        let (messages, transcript) = bunch.with_transcript();
+        assert!(messages.len() > 1);
+        assert_eq!(transcript, "Release notes\nbig old change in my_crate\nlet x = **y;\n- see docs (https://example.com) or https://example.org *now*, a*b\n");
+
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.add_lines("* one *two*\n  * nested\n> * quoted");
+        assert_eq!(mbb.build().with_transcript().1, "* one two\n  * nested\n* quoted\n");
+    }
+
+    #[test]
+    fn test_max_code_lines() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.max_code_lines(100).add_code_block_lines("rust", (0..500).map(|i| format!("let x = {};", i)));
+        let msgs = mbb.build().into_inner();
+        assert_eq!(msgs.len(), 5);
+        for (i, msg) in msgs.iter().enumerate() {
+            let opening = if i == 0 { "```rust\n" } else { "```rust\n// (continued)\n" };
+            assert!(msg.starts_with(opening));
+            assert!(msg.ends_with("```\n"));
+            assert_eq!(msg.lines().filter(|line| line.starts_with("let x")).count(), 100);
+            assert!(msg.contains(&format!("let x = {};\n", i * 100)));
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut mbb = MsgBunchBuilder::with_limit(20);
+        mbb.add_lines("# **Hello**\n\nwörld   of   *text*");
+        let bunch = mbb.build();
+        assert_eq!(bunch.summary(100), "Hello wörld of text");
+        assert_eq!(bunch.summary(19), "Hello wörld of text");
+        assert_eq!(bunch.summary(8), "Hello w…");
+        assert_eq!(bunch.summary(0), "");
+        assert_eq!(MsgBunchBuilder::new().build().summary(10), "");
+
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.add_lines("* one\n* two");
+        assert_eq!(mbb.build().summary(100), "* one * two");
+    }
+
+    #[test]
+    fn test_message_offsets() {
+        let bunch = MsgBunch::from_source("abcdéf", &[
+            SplitPoint { offset: 3, kind: SplitKind::Nice },
+            SplitPoint { offset: 3, kind: SplitKind::Nice },
+            SplitPoint { offset: 6, kind: SplitKind::Hard },
+        ], 10, LengthMode::Chars);
+        assert_eq!(bunch.iter().map(String::as_str).collect::<Vec<_>>(), vec!["abc", "", "dé", "f"]);
+        assert_eq!(bunch.message_offsets(), vec![0, 3, 3, 5]);
+        assert_eq!(bunch.message_index_at(0), Some(0));
+        assert_eq!(bunch.message_index_at(2), Some(0));
+        // the empty message holds no characters, so the one after it is found
+        assert_eq!(bunch.message_index_at(3), Some(2));
+        assert_eq!(bunch.message_index_at(4), Some(2));
+        assert_eq!(bunch.message_index_at(5), Some(3));
+        assert_eq!(bunch.message_index_at(6), None);
+        assert_eq!(MsgBunch::default().message_index_at(0), None);
+    }
+
+    #[test]
+    fn test_truncated_preview() {
+        let bunch = MsgBunch::from_source("héllo worldhi", &[SplitPoint { offset: 12, kind: SplitKind::Nice }], 20, LengthMode::Chars);
+        assert_eq!(bunch.truncated_preview(5, "…"), vec!["héllo…", "hi"]);
+        assert_eq!(bunch.truncated_preview(2, ""), vec!["hé", "hi"]);
+        assert_eq!(bunch.into_inner(), vec!["héllo world", "hi"]);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn test_build_with_stats() {
+        use std::time::Duration;
+
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.add_lines("short\nanother, one").add_string("aaaaaaaaaaaaaaa");
+        let (bunch, stats) = mbb.build_with_stats();
+        // the newlines put after the lines aren't counted
+        assert_eq!(stats.input_chars, 33);
+        assert_eq!(stats.sections, 2);
+        assert_eq!(stats.output_messages, bunch.iter().count());
+        assert_eq!(stats.output_messages, 4);
+        assert_eq!(stats.hard_splits, 1);
+
+        // neither is what's inserted into the content or put around it
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.add_string("@everyone").add_code_block_lines("rs", ["fn main() {}"]).add_user_list(&[(1, "anna")]);
+        assert_eq!(mbb.build_with_stats().1.input_chars, 9 + 12 + 4);
+        #[cfg(feature = "pulldown-cmark")]
+        {
+            let mut mbb = MsgBunchBuilder::new();
+            mbb.add_markdown_events(pulldown_cmark::Parser::new("# Title\n\n*text*"));
+            assert_eq!(mbb.build_with_stats().1.input_chars, 9);
+        }
+
+        // the clock starts when content is first added
+        let (_, stats) = MsgBunchBuilder::new().build_with_stats();
+        assert_eq!(stats.elapsed, Duration::ZERO);
+        let mut mbb = MsgBunchBuilder::new();
+        std::thread::sleep(Duration::from_millis(100));
+        mbb.add_string("text");
+        assert!(mbb.build_with_stats().1.elapsed < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_count_utf16() {
+        assert_eq!(MsgBunch::count_utf16(""), 0);
+        assert_eq!(MsgBunch::count_utf16(&"a".repeat(MSG_LIMIT)), 1);
+        assert_eq!(MsgBunch::count_utf16(&"😀".repeat(MSG_LIMIT / 2)), 1);
+        assert_eq!(MsgBunch::count_utf16(&"😀".repeat(MSG_LIMIT / 2 + 1)), 2);
+        // counted in characters the same text fits in one message
+        assert_eq!(MsgBunch::count_for_limits(&"😀".repeat(MSG_LIMIT / 2 + 1), &[MSG_LIMIT]), vec![1]);
+    }
+
+    #[test]
+    fn test_logical_segments() {
+        let bunch = MsgBunch::from_source("one, two\nthree fourfive", &[
+            SplitPoint { offset: 4, kind: SplitKind::Nice },
+            SplitPoint { offset: 9, kind: SplitKind::Nice },
+            SplitPoint { offset: 15, kind: SplitKind::Nice },
+            SplitPoint { offset: 19, kind: SplitKind::Hard },
+        ], 10, LengthMode::Chars);
+        assert_eq!(bunch.logical_segments(), vec!["one,", " two\n", "three ", "fourfive"]);
+    }
+
+    #[test]
+    fn test_thread_mode() {
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.first_message_limit(5).add_string("Title").break_message().add_string("aaaaaaaaaaaaaaa").break_message();
+        let (starter, replies) = mbb.build().thread_mode();
+        assert_eq!(starter, "Title");
+        assert_eq!(replies, vec!["aaaaaaaaaa", "aaaaa"]);
+
+        let bunch = MsgBunch::from_source("ab", &[SplitPoint { offset: 0, kind: SplitKind::Nice }], 10, LengthMode::Chars);
+        assert_eq!(bunch.thread_mode(), ("ab".to_owned(), vec![]));
+
+        let (starter, replies) = MsgBunchBuilder::new().build().thread_mode();
+        assert_eq!(starter, "");
+        assert!(replies.is_empty());
+    }
+
+    #[test]
+    fn test_to_decorated() {
+        let bunch = MsgBunch::from_source("onetwothree", &[
+            SplitPoint { offset: 3, kind: SplitKind::Nice },
+            SplitPoint { offset: 6, kind: SplitKind::Nice },
+        ], 10, LengthMode::Chars);
+        let decorated = bunch.to_decorated(
+            |i, total| format!("({}/{}) ", i + 1, total),
+            |i, total| if i + 1 < total { " ↓".to_owned() } else { String::new() },
+        );
+        assert_eq!(decorated, vec!["(1/3) one ↓", "(2/3) two ↓", "(3/3) three"]);
+        // the bunch itself is left as it was
+        assert_eq!(bunch.into_inner(), vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn test_into_decorated() {
+        let bunch = MsgBunch::from_source("onetwothree", &[
+            SplitPoint { offset: 3, kind: SplitKind::Nice },
+            SplitPoint { offset: 6, kind: SplitKind::Nice },
+        ], 10, LengthMode::Chars);
+        let decorated = bunch.into_decorated(|i, total| {
+            let suffix = if i + 1 < total { " ↓".to_owned() } else { String::new() };
+            (format!("({}/{}) ", i + 1, total), suffix)
+        });
+        assert_eq!(decorated, vec!["(1/3) one ↓", "(2/3) two ↓", "(3/3) three"]);
+        assert!(MsgBunch::default().into_decorated(|_, _| ("a".to_owned(), "b".to_owned())).is_empty());
+    }
+
+    #[test]
+    fn test_debug_layout() {
+        // split nicely after the first line, then hard in the middle of the second
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.prefer_blank_line_breaks(true).add_string("say \"é\"\naaaaaaaaaaaa\n");
+        let bunch = mbb.build();
+        assert_eq!(bunch.debug_layout(), concat!(
+            "[0] (8 chars): \"say \\\"é\\\"\\n\"\n",
+            "[1] (10 chars): \"aaaaaaaaaa\"\n",
+            "[2] (3 chars): \"aa\\n\"\n",
+        ));
+        assert_eq!(MsgBunch::default().debug_layout(), "");
+    }
+
+    #[test]
+    fn test_section_join() {
+        let mut mbb = MsgBunchBuilder::with_limit(20);
+        mbb.section_join(" | ");
+        for section in ["one", "two", "three, four, five, six", "seven"] {
+            mbb.begin_section().add_string(section).end_section();
+        }
+        // the separator doesn't start a message, nor is there one after the last section
+        assert_eq!(mbb.build().into_inner(), vec!["one | two", "three, four, five,", " six | seven"]);
+    }
+
+    #[test]
+    fn test_estimated_send_duration() {
+        use std::time::Duration;
+
+        let per_message = Duration::from_millis(1500);
+        let bunch = MsgBunch::from_source("onetwo \nthree", &[
+            SplitPoint { offset: 3, kind: SplitKind::Nice },
+            SplitPoint { offset: 6, kind: SplitKind::Nice },
+            SplitPoint { offset: 8, kind: SplitKind::Nice },
+        ], 10, LengthMode::Chars);
+        // the message with only whitespace won't be sent
+        assert_eq!(bunch.estimated_send_duration(per_message), Duration::from_millis(4500));
+
+        let one = MsgBunch::from_source("one", &[], 10, LengthMode::Chars);
+        assert_eq!(one.estimated_send_duration(per_message), per_message);
+        assert_eq!(MsgBunch::default().estimated_send_duration(per_message), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_sanitize_mentions() {
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.add_string("hi @everyone, @here and <@1234>");
+        assert_eq!(mbb.build().into_inner(), vec!["hi @\u{200B}everyone, @\u{200B}here and <@\u{200B}1234>"]);
+
+        // the inserted characters count against the limit
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.add_string("@everyone");
+        assert_eq!(mbb.build().into_inner(), vec!["@\u{200B}everyone"]);
+        let mut mbb = MsgBunchBuilder::with_limit(10);
+        mbb.add_string("@everyone!");
+        assert_eq!(mbb.build().into_inner(), vec!["@\u{200B}everyone", "!"]);
+
+        let mut mbb = MsgBunchBuilder::new();
+        mbb.sanitize_mentions(false).add_string("hi @everyone");
+        assert_eq!(mbb.build().into_inner(), vec!["hi @everyone"]);
+    }
+}
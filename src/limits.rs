@@ -0,0 +1,39 @@
+//! Limits of Discord messages and embeds, as documented at
+//! <https://discord.com/developers/docs/resources/message> and
+//! <https://discord.com/developers/docs/resources/message#embed-object-embed-limits>
+//!
+//! All of them are counted in characters.
+
+/// The character limit for the content of a message
+pub const MSG_LIMIT: usize = 2000;
+
+/// The character limit for the content of a message sent by a user with Nitro
+pub const MSG_LIMIT_NITRO: usize = 4000;
+
+/// The character limit for the title of an embed
+pub const EMBED_TITLE_LIMIT: usize = 256;
+
+/// The character limit for the description of an embed
+pub const EMBED_DESC_LIMIT: usize = 4096;
+
+/// The maximum amount of fields in an embed
+pub const EMBED_FIELDS_LIMIT: usize = 25;
+
+/// The character limit for the name of an embed field
+pub const EMBED_FIELD_NAME_LIMIT: usize = 256;
+
+/// The character limit for the value of an embed field
+pub const EMBED_FIELD_VALUE_LIMIT: usize = 1024;
+
+/// The character limit for the footer text of an embed
+pub const EMBED_FOOTER_LIMIT: usize = 2048;
+
+/// The character limit for the author name of an embed
+pub const EMBED_AUTHOR_NAME_LIMIT: usize = 256;
+
+/// The combined character limit for the title, description, field names, field values, footer text and author name
+/// of all embeds in a message
+pub const EMBED_TOTAL_LIMIT: usize = 6000;
+
+/// The maximum amount of embeds in a message
+pub const EMBEDS_PER_MSG_LIMIT: usize = 10;
@@ -0,0 +1,298 @@
+//! Adding Markdown parsed by `pulldown_cmark` to a `MsgBunchBuilder`
+
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Tag, TagEnd};
+
+use crate::MsgBunchBuilder;
+
+#[derive(Debug)]
+/// Renders events back to Markdown one block at a time
+struct Renderer {
+    block: String,
+    /// the language and lines of the code block in progress, which is added on its own so its fence is kept when it's split
+    code_block: Option<(String, String)>,
+    /// whether a block has been added, so the next one at the top level needs a blank line before it
+    after_block: bool,
+    at_line_start: bool,
+    quote_depth: usize,
+    /// the next number of every list we're in, `None` for unordered lists
+    lists: Vec<Option<u64>>,
+    link_dests: Vec<String>,
+    first_cell: bool,
+    skipping: bool,
+    /// what the text of the current list item is indented by, to line up after its marker
+    item_indent: String,
+}
+
+impl Renderer {
+    fn new() -> Self {
+        Renderer {
+            block: String::new(),
+            code_block: None,
+            after_block: false,
+            at_line_start: true,
+            quote_depth: 0,
+            lists: Vec::new(),
+            link_dests: Vec::new(),
+            first_cell: true,
+            skipping: false,
+            item_indent: String::new(),
+        }
+    }
+
+    /// Writes `s` to the block putting the quote and list indentation at the start of every line
+    fn write(&mut self, s: &str) {
+        if self.skipping {
+            return;
+        }
+        if let Some((_, code)) = &mut self.code_block {
+            code.push_str(s);
+            return;
+        }
+        for c in s.chars() {
+            if self.at_line_start {
+                self.write_prefix(self.lists.len());
+                self.at_line_start = false;
+            }
+            self.block.push(c);
+            if c == '\n' {
+                self.at_line_start = true;
+            }
+        }
+    }
+
+    fn write_prefix(&mut self, indent: usize) {
+        let prefix = self.prefix(indent);
+        self.block.push_str(&prefix);
+    }
+
+    /// The quote markers and `indent` levels of list indentation that start a line
+    fn prefix(&self, indent: usize) -> String {
+        "> ".repeat(self.quote_depth) + &"  ".repeat(indent)
+    }
+
+    /// Puts a blank line before a block at the top level, like between paragraphs, if it's not the first
+    fn begin_block(&mut self) {
+        if self.after_block && self.lists.is_empty() && self.quote_depth == 0 {
+            self.ensure_line_start();
+            self.write("\n");
+            self.after_block = false;
+        }
+    }
+
+    fn ensure_line_start(&mut self) {
+        if !self.at_line_start {
+            self.write("\n");
+        }
+    }
+
+    /// Adds the current block as a section
+    fn flush(&mut self, mbb: &mut MsgBunchBuilder) {
+        if !self.block.is_empty() {
+            // A list item split over messages continues at the same indentation
+            if !self.lists.is_empty() {
+                mbb.section_indent.clone_from(&self.item_indent);
+            }
+            mbb.begin_section().add_text(&self.block);
+            mbb.end_section();
+            mbb.section_indent.clear();
+            self.block.clear();
+            self.after_block = true;
+        }
+    }
+
+    /// Adds the current block as a section, unless it's part of a list item
+    fn end_block(&mut self, mbb: &mut MsgBunchBuilder) {
+        if self.lists.is_empty() {
+            self.flush(mbb);
+        }
+    }
+
+    fn start(&mut self, tag: Tag, mbb: &mut MsgBunchBuilder) {
+        match tag {
+            Tag::Paragraph => {
+                self.begin_block();
+                self.ensure_line_start();
+            }
+            Tag::Heading { level, .. } => {
+                self.begin_block();
+                self.ensure_line_start();
+                self.write(match level {
+                    HeadingLevel::H1 => "# ",
+                    HeadingLevel::H2 => "## ",
+                    _ => "### ",
+                });
+            }
+            Tag::BlockQuote(_) => {
+                self.begin_block();
+                self.ensure_line_start();
+                self.quote_depth += 1;
+            }
+            Tag::CodeBlock(kind) => {
+                self.begin_block();
+                self.ensure_line_start();
+                self.flush(mbb);
+                let lang = match kind {
+                    CodeBlockKind::Fenced(lang) => lang.into_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code_block = Some((lang, String::new()));
+            }
+            Tag::List(start) => {
+                self.begin_block();
+                // The text of the item this list is nested in goes into its own section
+                self.ensure_line_start();
+                self.flush(mbb);
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.ensure_line_start();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "- ".to_owned(),
+                };
+                let start = self.block.len();
+                self.write_prefix(self.lists.len().saturating_sub(1));
+                self.block.push_str(&marker);
+                self.item_indent = " ".repeat(self.block[start..].chars().count());
+                self.at_line_start = false;
+            }
+            Tag::FootnoteDefinition(name) => {
+                self.begin_block();
+                self.ensure_line_start();
+                self.write(&format!("[^{}]: ", name));
+            }
+            Tag::DefinitionListDefinition => {
+                self.ensure_line_start();
+                self.write(": ");
+            }
+            Tag::TableRow | Tag::TableHead => {
+                self.ensure_line_start();
+                self.first_cell = true;
+            }
+            Tag::TableCell => {
+                if !self.first_cell {
+                    self.write(" | ");
+                }
+                self.first_cell = false;
+            }
+            Tag::Emphasis => self.write("*"),
+            Tag::Strong => self.write("**"),
+            Tag::Strikethrough => self.write("~~"),
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                self.write("[");
+                self.link_dests.push(dest_url.into_string());
+            }
+            Tag::MetadataBlock(_) => self.skipping = true,
+            Tag::HtmlBlock | Tag::DefinitionList | Tag::Table(_) => self.begin_block(),
+            Tag::DefinitionListTitle
+            | Tag::Superscript
+            | Tag::Subscript => (),
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd, mbb: &mut MsgBunchBuilder) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::HtmlBlock | TagEnd::FootnoteDefinition => {
+                self.ensure_line_start();
+                self.end_block(mbb);
+            }
+            TagEnd::BlockQuote(_) => {
+                self.quote_depth -= 1;
+                self.end_block(mbb);
+            }
+            TagEnd::CodeBlock => {
+                if let Some((lang, code)) = self.code_block.take() {
+                    let prefix = self.prefix(self.lists.len());
+                    mbb.add_fenced_lines(&prefix, &lang, "", code.lines());
+                    self.after_block = true;
+                }
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+            }
+            TagEnd::Item => {
+                self.ensure_line_start();
+                self.flush(mbb);
+            }
+            TagEnd::DefinitionListTitle | TagEnd::DefinitionListDefinition | TagEnd::TableHead | TagEnd::TableRow => {
+                self.ensure_line_start();
+            }
+            TagEnd::DefinitionList | TagEnd::Table => self.end_block(mbb),
+            TagEnd::Emphasis => self.write("*"),
+            TagEnd::Strong => self.write("**"),
+            TagEnd::Strikethrough => self.write("~~"),
+            TagEnd::Link | TagEnd::Image => {
+                let dest = self.link_dests.pop().unwrap_or_default();
+                self.write("](");
+                self.write(&dest);
+                self.write(")");
+            }
+            TagEnd::MetadataBlock(_) => self.skipping = false,
+            TagEnd::TableCell | TagEnd::Superscript | TagEnd::Subscript => (),
+        }
+    }
+
+    fn event(&mut self, event: Event, mbb: &mut MsgBunchBuilder) {
+        // only the content of the events is input, not the syntax written around it
+        match &event {
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) | Event::Code(text)
+            | Event::InlineMath(text) | Event::DisplayMath(text) => mbb.count_input(Some(&**text)),
+            _ => (),
+        }
+        match event {
+            Event::Start(tag) => self.start(tag, mbb),
+            Event::End(tag) => self.end(tag, mbb),
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => self.write(&text),
+            Event::Code(code) => {
+                let fence = if code.contains('`') { "``" } else { "`" };
+                self.write(fence);
+                self.write(&code);
+                self.write(fence);
+            }
+            Event::InlineMath(math) => {
+                self.write("$");
+                self.write(&math);
+                self.write("$");
+            }
+            Event::DisplayMath(math) => {
+                self.write("$$");
+                self.write(&math);
+                self.write("$$");
+            }
+            Event::FootnoteReference(name) => self.write(&format!("[^{}]", name)),
+            Event::SoftBreak => self.write(" "),
+            Event::HardBreak => self.write("\n"),
+            Event::Rule => {
+                self.begin_block();
+                self.ensure_line_start();
+                self.write("\n");
+                self.end_block(mbb);
+            }
+            Event::TaskListMarker(checked) => self.write(if checked { "[x] " } else { "[ ] " }),
+        }
+    }
+}
+
+impl MsgBunchBuilder {
+    /// Adds Markdown parsed by `pulldown_cmark`, rendered back into Markdown that Discord understands
+    ///
+    /// Every block, like a paragraph, code block or list item, is added as its own section,
+    /// so a block is only split if it doesn't fit in a message by itself.
+    /// Blocks at the top level are separated by a blank line.
+    /// Code blocks that are too long are split between lines like in `add_code_block_lines`,
+    /// and a list item split in the middle of a line continues at its indentation in the next message.
+    ///
+    /// Headings deeper than `###` become `###` and Markdown that Discord doesn't support, like tables, is written as plain text.
+    pub fn add_markdown_events<'a, I: IntoIterator<Item = Event<'a>>>(&mut self, events: I) -> &mut Self {
+        self.count_input(None);
+        let mut renderer = Renderer::new();
+        for event in events {
+            renderer.event(event, self);
+        }
+        renderer.flush(self);
+        self
+    }
+}